@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::rule_parsing::{CompareTo, Condition, Op, StateRef};
+use crate::ruleset::Ruleset;
+
+/// Something suspicious about a ruleset that does not stop it from running
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct RulesetWarning {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for RulesetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// The values a neighbour count can take for a rule to match
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Range {
+    min: usize,
+    max: usize,
+}
+
+impl Range {
+    /// Matches no count at all
+    const NONE: Range = Range { min: 1, max: 0 };
+
+    fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    fn intersect(&self, other: &Range) -> Range {
+        Range {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+
+    fn contains(&self, other: &Range) -> bool {
+        other.is_empty() || (self.min <= other.min && other.max <= self.max)
+    }
+}
+
+/// A rule's conditions reduced to a range per counted reference, plus any conditions which
+/// cannot be expressed that way
+struct Constraints<'a> {
+    ranges: Vec<(&'a StateRef, Range)>,
+    other: Vec<&'a Condition>,
+}
+
+impl<'a> Constraints<'a> {
+    fn new(conditions: &'a [Condition], neighbours: usize) -> Self {
+        let all = Range {
+            min: 0,
+            max: neighbours,
+        };
+        let mut constraints = Constraints {
+            ranges: vec![],
+            other: vec![],
+        };
+
+        for condition in conditions {
+            let range = match (&condition.op, &condition.compare_to) {
+                (Op::Eq, CompareTo::Value(v)) => Range { min: *v, max: *v },
+                (Op::Gt, CompareTo::Value(v)) => match v.checked_add(1) {
+                    Some(min) => Range {
+                        min,
+                        max: neighbours,
+                    },
+                    None => Range::NONE,
+                },
+                (Op::Ge, CompareTo::Value(v)) => Range {
+                    min: *v,
+                    max: neighbours,
+                },
+                (Op::Lt, CompareTo::Value(v)) => match v.checked_sub(1) {
+                    Some(max) => Range { min: 0, max },
+                    None => Range::NONE,
+                },
+                (Op::Le, CompareTo::Value(v)) => Range { min: 0, max: *v },
+                _ => {
+                    constraints.other.push(condition);
+                    continue;
+                }
+            };
+
+            match constraints
+                .ranges
+                .iter_mut()
+                .find(|(state, _)| *state == &condition.state)
+            {
+                Some((_, existing)) => *existing = existing.intersect(&range),
+                None => constraints
+                    .ranges
+                    .push((&condition.state, all.intersect(&range))),
+            }
+        }
+
+        constraints
+    }
+
+    fn range(&self, state: &StateRef) -> Option<Range> {
+        self.ranges
+            .iter()
+            .find(|(s, _)| *s == state)
+            .map(|(_, range)| *range)
+    }
+
+    fn is_satisfiable(&self) -> bool {
+        self.ranges.iter().all(|(_, range)| !range.is_empty())
+    }
+
+    /// Whether every cell matching `other` also matches these constraints
+    fn covers(&self, other: &Constraints) -> bool {
+        self.ranges.iter().all(|(state, range)| {
+            other
+                .range(state)
+                .is_some_and(|other_range| range.contains(&other_range))
+        }) && self.other.iter().all(|c| other.other.contains(c))
+    }
+}
+
+impl Ruleset {
+    /// Looks for states and rules which can never have any effect
+    pub fn analyse<'a>(&'a self) -> Vec<RulesetWarning> {
+        let mut warnings = vec![];
+        let neighbours = self.neighbourhood.get_neighbours().len();
+
+        // Maps generated states back to the family and rule templates they came from
+        let family_of = |state: &str| {
+            self.families.iter().find_map(|(name, family)| {
+                (0..family.count)
+                    .any(|i| family.member(name, i) == state)
+                    .then_some((name, family))
+            })
+        };
+        let state_path = |state: &str| match family_of(state) {
+            Some((family, _)) => format!("families.{family}"),
+            None => format!("states.{state}"),
+        };
+        let rule_template = |state: &str, index: usize, source: &'a str| -> &'a str {
+            family_of(state)
+                .and_then(|(_, family)| family.rules.get_index(index))
+                .map_or(source, |(template, _)| template.as_str())
+        };
+
+        let targets: HashSet<&str> = self
+            .states
+            .iter()
+            .flat_map(|(name, state)| {
+                state
+                    .parsed_rules()
+                    .map(|(_, _, outcome)| outcome)
+                    .chain(state.otherwise.as_ref())
+                    .map(|outcome| outcome.next.as_str())
+                    .filter(move |next| next != name)
+            })
+            .collect();
+
+        for (name, state) in &self.states {
+            if *name != self.default_state && !targets.contains(name.as_str()) {
+                warnings.push(RulesetWarning {
+                    path: state_path(name),
+                    message: format!(
+                        "state `{name}` is unreachable, no other state transitions to it"
+                    ),
+                });
+            }
+
+            if state.rules.is_empty() && state.otherwise.is_none() {
+                warnings.push(RulesetWarning {
+                    path: state_path(name),
+                    message: format!("state `{name}` has no rules or otherwise, so never changes"),
+                });
+            }
+
+            let mut earlier: Vec<(&str, Constraints)> = vec![];
+
+            for (index, (source, conditions, _)) in state.parsed_rules().enumerate() {
+                let source = rule_template(name, index, source);
+                let path = format!("{}.rules[{source:?}]", state_path(name));
+                let constraints = Constraints::new(conditions, neighbours);
+
+                if !constraints.is_satisfiable() {
+                    warnings.push(RulesetWarning {
+                        path,
+                        message: format!(
+                            "rule can never match with {neighbours} neighbours in the neighbourhood"
+                        ),
+                    });
+                    continue;
+                }
+
+                if let Some((shadowing, _)) = earlier.iter().find(|(_, e)| e.covers(&constraints)) {
+                    warnings.push(RulesetWarning {
+                        path,
+                        message: format!(
+                            "rule is shadowed by the earlier rule {shadowing:?} and can never apply"
+                        ),
+                    });
+                    continue;
+                }
+
+                earlier.push((source, constraints));
+            }
+        }
+
+        // Family members share their templates, so only report each problem once
+        let mut seen = HashSet::new();
+        warnings.retain(|w| seen.insert(w.clone()));
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(json: &str) -> Vec<String> {
+        let ruleset = Ruleset::from_json(json).expect("Ruleset should be valid");
+        ruleset.analyse().iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_bundled_rulesets_are_clean() {
+        for json in [
+            include_str!("../rulesets/game_of_life.json"),
+            include_str!("../rulesets/highlife.json"),
+            include_str!("../rulesets/immigration.json"),
+            include_str!("../rulesets/cyclic.json"),
+        ] {
+            assert_eq!(messages(json), Vec::<String>::new());
+        }
+
+        assert_eq!(
+            messages(include_str!("../rulesets/wireworld.json")),
+            vec!["states.empty: state `empty` has no rules or otherwise, so never changes"]
+        );
+    }
+
+    #[test]
+    fn test_unsatisfiable_and_shadowed_rules() {
+        let warnings = messages(
+            r#"{
+                "default_state": "empty",
+                "neighbourhood": { "Moore": { "range": 1, "include_self": false } },
+                "states": {
+                    "empty": {
+                        "colour": [0, 0, 0, 0],
+                        "rules": {
+                            "$head > 8": { "next": "head" },
+                            "$head > 18446744073709551615": { "next": "head" },
+                            "$head >= 2 ; $head < 1": { "next": "head" },
+                            "$head >= 1": { "next": "head" },
+                            "$head == 3 ; $empty > 2": { "next": "tail" },
+                            "$head >= 1 ; $tail == 2": { "next": "tail" },
+                            "$tail == $head": { "next": "tail" },
+                            "$tail == $head ; $empty == 0": { "next": "tail" }
+                        }
+                    },
+                    "head": { "colour": [0, 0, 0, 0], "rules": {}, "otherwise": { "next": "tail" } },
+                    "tail": { "colour": [0, 0, 0, 0], "rules": {}, "otherwise": { "next": "empty" } },
+                    "orphan": { "colour": [0, 0, 0, 0], "rules": {}, "otherwise": { "next": "empty" } }
+                }
+            }"#,
+        );
+
+        assert_eq!(
+            warnings,
+            vec![
+                "states.empty.rules[\"$head > 8\"]: rule can never match with 8 neighbours in the neighbourhood",
+                "states.empty.rules[\"$head > 18446744073709551615\"]: rule can never match with 8 neighbours in the neighbourhood",
+                "states.empty.rules[\"$head >= 2 ; $head < 1\"]: rule can never match with 8 neighbours in the neighbourhood",
+                "states.empty.rules[\"$head == 3 ; $empty > 2\"]: rule is shadowed by the earlier rule \"$head >= 1\" and can never apply",
+                "states.empty.rules[\"$head >= 1 ; $tail == 2\"]: rule is shadowed by the earlier rule \"$head >= 1\" and can never apply",
+                "states.empty.rules[\"$tail == $head ; $empty == 0\"]: rule is shadowed by the earlier rule \"$tail == $head\" and can never apply",
+                "states.orphan: state `orphan` is unreachable, no other state transitions to it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_family_warnings_are_reported_once() {
+        let warnings = messages(
+            r#"{
+                "default_state": "step_1",
+                "neighbourhood": "Individual",
+                "families": {
+                    "step": {
+                        "count": 4,
+                        "colours": [[0, 0, 0, 0]],
+                        "rules": {
+                            "$next >= 1": { "next": "$next" },
+                            "$next == 1": { "next": "$prev" }
+                        }
+                    }
+                }
+            }"#,
+        );
+
+        assert_eq!(
+            warnings,
+            vec!["families.step.rules[\"$next == 1\"]: rule is shadowed by the earlier rule \"$next >= 1\" and can never apply"]
+        );
+    }
+}
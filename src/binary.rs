@@ -0,0 +1,356 @@
+/*
+A compact binary encoding of worlds, for storing many generations
+
+HEADER := "GOLW" VERSION FLAGS
+FLAGS := bit 0 set when every frame body is deflate compressed
+FILE := HEADER FRAME*
+FRAME := varint(body length) BODY
+BODY := varint(width) varint(height) varint(generation) PALETTE RUN*
+PALETTE := varint(count) (varint(name length) name r g b a)*
+RUN := varint(palette index) varint(cell count)
+
+Runs cover every cell row by row. Varints are LEB128: 7 bits per byte, low bits first, with
+the top bit set on every byte but the last.
+*/
+
+use std::io::{self, Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::{ruleset::Ruleset, world::World};
+
+static MAGIC: &[u8; 4] = b"GOLW";
+const VERSION: u8 = 1;
+const COMPRESSED: u8 = 1;
+/// Most cells a frame can have, far more than any world the app makes, so that corrupt data
+/// is refused instead of exhausting memory
+const MAX_CELLS: u64 = 1 << 24;
+/// Largest decompressed frame body, for the same reason
+const MAX_BODY: u64 = 1 << 26;
+
+/// Appends generations to a stream, one frame each
+pub(crate) struct GenerationWriter<W: Write> {
+    writer: W,
+    compress: bool,
+}
+
+impl<W: Write> GenerationWriter<W> {
+    pub fn new(mut writer: W, compress: bool) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, if compress { COMPRESSED } else { 0 }])?;
+        Ok(GenerationWriter { writer, compress })
+    }
+
+    pub fn write(&mut self, world: &World) -> io::Result<()> {
+        let mut body = vec![];
+        let (palette, cells) = world.palette();
+
+        write_varint(&mut body, world.width as u64);
+        write_varint(&mut body, world.height as u64);
+        write_varint(&mut body, world.generation);
+        write_varint(&mut body, palette.len() as u64);
+        for (name, colour) in &palette {
+            write_varint(&mut body, name.len() as u64);
+            body.extend_from_slice(name.as_bytes());
+            body.extend_from_slice(colour);
+        }
+        for run in cells.chunk_by(|a, b| a == b) {
+            write_varint(&mut body, run[0] as u64);
+            write_varint(&mut body, run.len() as u64);
+        }
+
+        if self.compress {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(&body)?;
+            body = encoder.finish()?;
+        }
+
+        let mut length = vec![];
+        write_varint(&mut length, body.len() as u64);
+        self.writer.write_all(&length)?;
+        self.writer.write_all(&body)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads generations back one at a time into the same world, so long runs never need to fit
+/// in memory
+pub(crate) struct GenerationReader<R: Read> {
+    reader: R,
+    compressed: bool,
+    world: World,
+}
+
+impl<R: Read> GenerationReader<R> {
+    pub fn new(mut reader: R, ruleset: Ruleset) -> Result<Self, String> {
+        let mut header = [0; 6];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| "Data is too short to be a world".to_string())?;
+
+        if &header[..4] != MAGIC {
+            return Err("Data is not an encoded world".to_string());
+        }
+        if header[4] != VERSION {
+            return Err(format!("Unsupported world encoding version {}", header[4]));
+        }
+
+        Ok(GenerationReader {
+            reader,
+            compressed: header[5] & COMPRESSED != 0,
+            world: World::new((0, 0), ruleset).ok_or("Ruleset has no default state")?,
+        })
+    }
+
+    /// Reads the next generation, or `None` at the end of the data
+    pub fn next_frame(&mut self) -> Option<Result<&World, String>> {
+        match read_varint(&mut self.reader) {
+            Ok(None) => None,
+            Ok(Some(length)) => Some(self.read_frame(length)),
+            Err(e) => Some(Err(e.to_string())),
+        }
+    }
+
+    fn read_frame(&mut self, length: u64) -> Result<&World, String> {
+        if length > MAX_BODY {
+            return Err("Frame is too large".to_string());
+        }
+        let mut body = vec![];
+        (&mut self.reader)
+            .take(length)
+            .read_to_end(&mut body)
+            .map_err(|e| e.to_string())?;
+        if body.len() as u64 != length {
+            return Err("Frame is truncated".to_string());
+        }
+        if self.compressed {
+            let mut decompressed = vec![];
+            DeflateDecoder::new(&body[..])
+                .take(MAX_BODY + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("Invalid compressed frame: {e}"))?;
+            if decompressed.len() as u64 > MAX_BODY {
+                return Err("Frame is too large".to_string());
+            }
+            body = decompressed;
+        }
+
+        let mut body = &body[..];
+        let varint = |body: &mut &[u8]| {
+            read_varint(body)
+                .map_err(|_| "Frame is truncated".to_string())?
+                .ok_or_else(|| "Frame is truncated".to_string())
+        };
+
+        let width = varint(&mut body)?;
+        let height = varint(&mut body)?;
+        let size = width
+            .checked_mul(height)
+            .filter(|&size| size <= MAX_CELLS)
+            .ok_or_else(|| format!("Frame size {width}x{height} is too large"))?
+            as usize;
+        let generation = varint(&mut body)?;
+
+        let mut palette = vec![];
+        for _ in 0..varint(&mut body)? {
+            let length = varint(&mut body)?;
+            if (body.len() as u64).saturating_sub(4) < length {
+                return Err("Frame is truncated".to_string());
+            }
+            let length = length as usize;
+            let name = std::str::from_utf8(&body[..length])
+                .map_err(|_| "Invalid state name".to_string())?;
+            let colour = [
+                body[length],
+                body[length + 1],
+                body[length + 2],
+                body[length + 3],
+            ];
+            palette.push((name.to_string(), colour));
+            body = &body[length + 4..];
+        }
+
+        let mut cells = Vec::with_capacity(size);
+        while !body.is_empty() {
+            let index = varint(&mut body)? as usize;
+            let count = varint(&mut body)?;
+            if count > (size - cells.len()) as u64 {
+                return Err("Frame has more cells than its size".to_string());
+            }
+            cells.extend(std::iter::repeat_n(index, count as usize));
+        }
+
+        let world = &mut self.world;
+        (world.width, world.height) = (width as usize, height as usize);
+        if let Err(e) = world.set_palette_cells(&palette, &cells) {
+            // Don't leave the world's size disagreeing with its cells
+            (world.width, world.height) = (0, 0);
+            world.cells.clear();
+            return Err(e);
+        }
+        world.generation = generation;
+        Ok(world)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a varint, or `None` if the reader is already at its end
+fn read_varint(reader: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+        }
+        if shift > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Varint is too long",
+            ));
+        }
+
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pattern::rle,
+        test_utils::{self, WIREWORLD},
+    };
+
+    fn encode(world: &World, compress: bool) -> Vec<u8> {
+        let mut writer = GenerationWriter::new(vec![], compress).unwrap();
+        writer.write(world).unwrap();
+        writer.into_inner()
+    }
+
+    fn decode(bytes: &[u8], ruleset: &Ruleset) -> Result<World, String> {
+        let mut reader = GenerationReader::new(bytes, ruleset.clone())?;
+        let decoded = reader
+            .next_frame()
+            .unwrap_or_else(|| Err("No world in data".to_string()))?;
+
+        let mut world = World::new((decoded.width, decoded.height), ruleset.clone()).unwrap();
+        world.cells = decoded.cells.clone();
+        world.generation = decoded.generation;
+        Ok(world)
+    }
+
+    fn wireworld() -> World {
+        let mut world = test_utils::world(WIREWORLD, (40, 30), None);
+        let circuit = rle::parse("x = 8, y = 3\n8C$ABC2.3C$8C!").unwrap();
+        world.stamp(&circuit, 2, 1).unwrap();
+        world
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value);
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), Some(value));
+        }
+        assert_eq!(read_varint(&mut &[][..]).unwrap(), None);
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut world = wireworld();
+        world.next_generation();
+        world.cells[0].colour = macroquad::prelude::Color::from_rgba(1, 2, 3, 4);
+
+        for compress in [false, true] {
+            let bytes = encode(&world, compress);
+            let decoded = decode(&bytes, &world.ruleset).unwrap();
+
+            assert_eq!(decoded.cells, world.cells);
+            assert_eq!((decoded.width, decoded.height), (40, 30));
+            assert_eq!(decoded.generation, 1);
+            // Far smaller than a byte per cell
+            assert!(bytes.len() < 200, "{} bytes", bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_stream_generations() {
+        let mut world = wireworld();
+        let mut writer = GenerationWriter::new(vec![], true).unwrap();
+        let mut expected = vec![];
+        for _ in 0..5 {
+            writer.write(&world).unwrap();
+            expected.push(world.cells.clone());
+            world.next_generation();
+        }
+        let bytes = writer.into_inner();
+
+        let mut reader = GenerationReader::new(&bytes[..], world.ruleset.clone()).unwrap();
+        let mut frames = 0;
+        while let Some(decoded) = reader.next_frame() {
+            let decoded = decoded.unwrap();
+            assert_eq!(decoded.generation, frames as u64);
+            assert_eq!(decoded.cells, expected[frames]);
+            frames += 1;
+        }
+        assert_eq!(frames, 5);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let world = wireworld();
+        let bytes = encode(&world, false);
+
+        assert!(decode(b"GOL", &world.ruleset).is_err());
+        assert!(decode(b"NOPE\x01\x00", &world.ruleset).is_err());
+        assert!(decode(b"GOLW\x02\x00", &world.ruleset).is_err());
+        assert!(decode(b"GOLW\x01\x00", &world.ruleset).is_err());
+        assert!(decode(&bytes[..bytes.len() - 1], &world.ruleset).is_err());
+
+        let game_of_life =
+            Ruleset::from_json(include_str!("../rulesets/game_of_life.json")).unwrap();
+        assert!(decode(&bytes, &game_of_life).is_err());
+
+        // Sizes and counts from corrupt data are refused rather than overflowing or
+        // exhausting memory
+        let frame = |body: &[u64]| {
+            let mut frame = vec![];
+            for value in body {
+                write_varint(&mut frame, *value);
+            }
+            let mut bytes = b"GOLW\x01\x00".to_vec();
+            write_varint(&mut bytes, frame.len() as u64);
+            bytes.extend(frame);
+            bytes
+        };
+        let huge = frame(&[u64::MAX, u64::MAX, 0, 0]);
+        assert!(matches!(decode(&huge, &world.ruleset), Err(e) if e.contains("too large")));
+        let overflowing = frame(&[1 << 32, 1 << 32, 0, 0]);
+        assert!(decode(&overflowing, &world.ruleset).is_err());
+        let long_name = frame(&[1, 1, 0, 1, u64::MAX]);
+        assert!(decode(&long_name, &world.ruleset).is_err());
+        let long_run = frame(&[2, 2, 0, 0, 0, u64::MAX]);
+        assert!(decode(&long_run, &world.ruleset).is_err());
+    }
+}
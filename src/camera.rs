@@ -0,0 +1,129 @@
+use macroquad::prelude::*;
+
+/// Pixels per cell the camera can zoom between
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 200.0;
+
+/// Maps between cells in the world and pixels on the screen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Camera {
+    /// The point in the world, in cells, at the top left of the screen
+    pub offset: Vec2,
+    /// Pixels per cell
+    pub zoom: f32,
+}
+
+impl Camera {
+    pub fn new(zoom: f32) -> Self {
+        Camera {
+            offset: Vec2::ZERO,
+            zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+        }
+    }
+
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        self.offset + screen / self.zoom
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        (world - self.offset) * self.zoom
+    }
+
+    /// The cell under a point on the screen
+    pub fn cell_at(&self, screen: Vec2) -> (isize, isize) {
+        let world = self.screen_to_world(screen).floor();
+        (world.x as isize, world.y as isize)
+    }
+
+    /// The range of cells at least partly on a screen of the given size, as (min, max)
+    /// corners with the max exclusive
+    pub fn visible_cells(&self, screen_size: Vec2) -> ((isize, isize), (isize, isize)) {
+        let min = self.offset.floor();
+        let max = self.screen_to_world(screen_size).ceil();
+        (
+            (min.x as isize, min.y as isize),
+            (max.x as isize, max.y as isize),
+        )
+    }
+
+    /// Sets the zoom, within the camera's limits, keeping the centre of the screen still
+    pub fn set_zoom(&mut self, zoom: f32, screen_size: Vec2) {
+        self.zoom_at(screen_size / 2.0, zoom / self.zoom);
+    }
+
+    /// Multiplies the zoom while keeping the point under `screen` still
+    pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+        let anchor = self.screen_to_world(screen);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = anchor - screen / self.zoom;
+    }
+
+    /// Moves the view by a distance in pixels, as when dragging the world
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.offset -= screen_delta / self.zoom;
+    }
+
+    /// Zooms and centres to show a rectangle of cells with a small margin
+    pub fn fit(&mut self, rect: Rect, screen_size: Vec2) {
+        let margin = 2.0;
+        let size = vec2(rect.w, rect.h) + margin * 2.0;
+        self.zoom = (screen_size / size).min_element().clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = rect.center() - screen_size / self.zoom / 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_at() {
+        let camera = Camera {
+            offset: vec2(10.0, -5.0),
+            zoom: 4.0,
+        };
+
+        assert_eq!(camera.cell_at(vec2(0.0, 0.0)), (10, -5));
+        assert_eq!(camera.cell_at(vec2(7.9, 4.0)), (11, -4));
+        assert_eq!(camera.cell_at(vec2(-1.0, -1.0)), (9, -6));
+        assert_eq!(camera.world_to_screen(vec2(12.0, -3.0)), vec2(8.0, 8.0));
+
+        let (min, max) = camera.visible_cells(vec2(40.0, 21.0));
+        assert_eq!((min, max), ((10, -5), (20, 1)));
+    }
+
+    #[test]
+    fn test_zoom_keeps_cursor_still() {
+        let mut camera = Camera::new(8.0);
+        let cursor = vec2(120.0, 80.0);
+        let before = camera.screen_to_world(cursor);
+
+        camera.zoom_at(cursor, 0.5);
+        assert_eq!(camera.zoom, 4.0);
+        assert!((camera.screen_to_world(cursor) - before).length() < 1e-4);
+
+        camera.zoom_at(cursor, 0.0001);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+
+        // Setting the zoom keeps the centre of the screen still, within the same limits
+        let screen = vec2(400.0, 300.0);
+        let centre = camera.screen_to_world(screen / 2.0);
+        camera.set_zoom(16.0, screen);
+        assert_eq!(camera.zoom, 16.0);
+        assert!((camera.screen_to_world(screen / 2.0) - centre).length() < 1e-3);
+        camera.set_zoom(1000.0, screen);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn test_pan_and_fit() {
+        let mut camera = Camera::new(2.0);
+        camera.pan(vec2(10.0, -4.0));
+        assert_eq!(camera.offset, vec2(-5.0, 2.0));
+
+        camera.fit(Rect::new(100.0, 50.0, 16.0, 6.0), vec2(400.0, 300.0));
+        assert_eq!(camera.zoom, 20.0);
+        let centre = camera.screen_to_world(vec2(200.0, 150.0));
+        assert!((centre - vec2(108.0, 53.0)).length() < 1e-4);
+    }
+}
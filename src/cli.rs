@@ -0,0 +1,390 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+};
+
+use macroquad::rand;
+
+use crate::{
+    binary::{GenerationReader, GenerationWriter},
+    pattern::{self, MAX_CELLS},
+    render::{self, GifWriter, RenderOptions},
+    ruleset::Ruleset,
+    world::World,
+    BUNDLED_RULESETS,
+};
+
+static USAGE: &str = "\
+Usage: game-of-life [COMMAND | RULESET.json]
+
+Runs the simulator when no command is given, starting with RULESET.json if given. Rulesets
+in ./rulesets and the user config directory, such as ~/.config/game-of-life/rulesets, are
+listed alongside the built-in ones.
+
+Commands:
+    lint [--deny-warnings] [RULESET...]
+                         Check rulesets for errors and rules which can never apply,
+                         checking the bundled rulesets if none are given. Only errors
+                         fail unless --deny-warnings is given
+    render [OPTIONS] OUTPUT
+                         Run a simulation without a window, writing each generation to
+                         numbered PNG frames, an animated GIF if OUTPUT ends in .gif, or
+                         a compact recording if OUTPUT ends in .golw
+    help                 Print this message
+
+Render options:
+    --ruleset RULESET    A bundled ruleset name or a ruleset file [default: Game of Life]
+    --pattern FILE       Place a pattern in the middle of an empty world instead of
+                         starting from random cells
+    --replay FILE        Render the generations in a .golw recording instead of
+                         running a simulation
+    --size WxH           World size in cells [default: 64x64]
+    --generations N      Generations to run after the first frame [default: 50]
+    --cell-size N        Pixels per cell [default: 8]
+    --grid               Draw grid lines between cells
+    --delay MS           Time between GIF frames [default: 100]
+    --seed N             Seed for the random starting cells [default: 0]";
+
+/// Runs the command given on the command line, returning its exit code, or `None` if the
+/// simulator should be started instead
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?;
+    if command.ends_with(".json") {
+        return None;
+    }
+
+    match command.as_str() {
+        "lint" => Some(lint(&args[1..])),
+        "render" => Some(match RenderArgs::parse(&args[1..]) {
+            Ok(render_args) => render(&render_args),
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                2
+            }
+        }),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Some(0)
+        }
+        _ => {
+            eprintln!("Unknown command {command}\n\n{USAGE}");
+            Some(2)
+        }
+    }
+}
+
+/// Prints every error and warning in the given rulesets, failing if there were any errors,
+/// or any warnings with `--deny-warnings`
+fn lint(args: &[String]) -> i32 {
+    let deny_warnings = args.iter().any(|arg| arg == "--deny-warnings");
+    let paths: Vec<&String> = args
+        .iter()
+        .filter(|arg| *arg != "--deny-warnings")
+        .collect();
+    let mut sources = vec![];
+    let mut problems = 0;
+
+    if paths.is_empty() {
+        for (name, json) in BUNDLED_RULESETS {
+            sources.push((name.to_string(), json.to_string()));
+        }
+    } else {
+        for path in paths {
+            match fs::read_to_string(path) {
+                Ok(json) => sources.push((path.to_string(), json)),
+                Err(e) => {
+                    eprintln!("{path}: error: {e}");
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    for (name, json) in sources {
+        match Ruleset::from_json(&json) {
+            Ok(ruleset) => {
+                for warning in ruleset.analyse() {
+                    println!("{name}: warning: {warning}");
+                    if deny_warnings {
+                        problems += 1;
+                    }
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    println!("{name}: error: {error}");
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    if problems == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+#[derive(PartialEq, Debug)]
+struct RenderArgs {
+    output: String,
+    ruleset: String,
+    pattern: Option<String>,
+    replay: Option<String>,
+    size: (usize, usize),
+    generations: usize,
+    cell_size: u32,
+    grid_lines: bool,
+    delay: u32,
+    seed: u64,
+}
+
+impl RenderArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut render_args = RenderArgs {
+            output: String::new(),
+            ruleset: BUNDLED_RULESETS[0].0.to_string(),
+            pattern: None,
+            replay: None,
+            size: (64, 64),
+            generations: 50,
+            cell_size: 8,
+            grid_lines: false,
+            delay: 100,
+            seed: 0,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {arg}"))
+            };
+            let number = |value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid number {value:?} for {arg}"))
+            };
+            let small_number = |value: &str| {
+                u32::try_from(number(value)?).map_err(|_| {
+                    format!("{value} is too large for {arg}, the most is {}", u32::MAX)
+                })
+            };
+
+            match arg.as_str() {
+                "--ruleset" => render_args.ruleset = value()?.clone(),
+                "--pattern" => render_args.pattern = Some(value()?.clone()),
+                "--replay" => render_args.replay = Some(value()?.clone()),
+                "--size" => {
+                    let size = value()?;
+                    render_args.size = size
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|&(w, h)| w > 0 && h > 0)
+                        .ok_or_else(|| format!("Invalid size {size:?}, expected WxH"))?;
+                    let (w, h) = render_args.size;
+                    if w.checked_mul(h).is_none_or(|cells| cells > MAX_CELLS) {
+                        return Err(format!("Size {size} is larger than {MAX_CELLS} cells"));
+                    }
+                }
+                "--generations" => render_args.generations = number(value()?)? as usize,
+                "--cell-size" => render_args.cell_size = small_number(value()?)?.max(1),
+                "--grid" => render_args.grid_lines = true,
+                "--delay" => render_args.delay = small_number(value()?)?,
+                "--seed" => render_args.seed = number(value()?)?,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ if render_args.output.is_empty() => render_args.output = arg.clone(),
+                _ => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+
+        if render_args.output.is_empty() {
+            return Err("Missing OUTPUT".to_string());
+        }
+        Ok(render_args)
+    }
+}
+
+/// Runs a simulation and writes its generations as images
+fn render(args: &RenderArgs) -> i32 {
+    match render_frames(args) {
+        Ok(frames) => {
+            println!("Rendered {frames} frames to {}", args.output);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            1
+        }
+    }
+}
+
+fn render_frames(args: &RenderArgs) -> Result<usize, String> {
+    let json = match BUNDLED_RULESETS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&args.ruleset))
+    {
+        Some((_, json)) => json.to_string(),
+        None => fs::read_to_string(&args.ruleset)
+            .map_err(|e| format!("Could not read {}: {e}", args.ruleset))?,
+    };
+    let ruleset = Ruleset::from_json(&json).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        format!("Invalid ruleset {}: {}", args.ruleset, errors.join(", "))
+    })?;
+
+    let options = RenderOptions {
+        cell_size: args.cell_size,
+        grid_lines: args.grid_lines,
+    };
+    let mut output = Output::create(args)?;
+    let mut frames = 0;
+
+    if let Some(path) = &args.replay {
+        let file = File::open(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        let mut reader = GenerationReader::new(BufReader::new(file), ruleset)?;
+        while let Some(world) = reader.next_frame() {
+            output.write(world?, frames, &options)?;
+            frames += 1;
+        }
+    } else {
+        let mut world = World::new(args.size, ruleset).ok_or("Ruleset has no default state")?;
+        rand::srand(args.seed);
+        match &args.pattern {
+            Some(path) => {
+                let pattern = pattern::read_file(path, world.width, world.height)?;
+                let x = (world.width - pattern.width.min(world.width)) / 2;
+                let y = (world.height - pattern.height.min(world.height)) / 2;
+                world.stamp(&pattern, x as isize, y as isize)?;
+            }
+            None => world.randomise(),
+        }
+
+        for generation in 0..=args.generations {
+            if generation > 0 {
+                world.next_generation();
+            }
+            output.write(&world, frames, &options)?;
+            frames += 1;
+        }
+    }
+
+    output.finish()?;
+    Ok(frames)
+}
+
+/// Where rendered generations go, picked by the extension of OUTPUT
+enum Output {
+    Gif(GifWriter),
+    /// Numbered PNG frames, unless only one generation is rendered
+    Png {
+        path: String,
+        numbered: bool,
+    },
+    Recording(GenerationWriter<BufWriter<File>>, String),
+}
+
+impl Output {
+    fn create(args: &RenderArgs) -> Result<Output, String> {
+        let path = &args.output;
+        if path.ends_with(".gif") {
+            Ok(Output::Gif(GifWriter::create(path, args.delay)?))
+        } else if path.ends_with(".golw") {
+            let file = File::create(path).map_err(|e| format!("Could not write {path}: {e}"))?;
+            let writer = GenerationWriter::new(BufWriter::new(file), true)
+                .map_err(|e| format!("Could not write {path}: {e}"))?;
+            Ok(Output::Recording(writer, path.clone()))
+        } else {
+            Ok(Output::Png {
+                path: path.clone(),
+                numbered: args.replay.is_some() || args.generations > 0,
+            })
+        }
+    }
+
+    fn write(
+        &mut self,
+        world: &World,
+        frame: usize,
+        options: &RenderOptions,
+    ) -> Result<(), String> {
+        match self {
+            Output::Gif(gif) => gif.add_frame(render::render(world, options)?),
+            Output::Png { path, numbered } => {
+                let path = if *numbered {
+                    let stem = path.strip_suffix(".png").unwrap_or(path);
+                    format!("{stem}_{frame:04}.png")
+                } else {
+                    path.clone()
+                };
+                render::save_png(&render::render(world, options)?, &path)
+            }
+            Output::Recording(writer, path) => writer
+                .write(world)
+                .map_err(|e| format!("Could not write {path}: {e}")),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Output::Recording(writer, path) => writer
+                .into_inner()
+                .flush()
+                .map_err(|e| format!("Could not write {path}: {e}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_lint_fails_on_warnings_only_when_denied() {
+        // Wireworld's empty state never changing is only a warning
+        assert_eq!(lint(&[]), 0);
+        assert_eq!(lint(&args("--deny-warnings")), 1);
+        assert_eq!(lint(&args("missing.json")), 1);
+    }
+
+    #[test]
+    fn test_parse_render_args() {
+        let parsed = RenderArgs::parse(&args(
+            "--ruleset wireworld --size 20x10 --grid out.gif --generations 5 --cell-size 4",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            parsed,
+            RenderArgs {
+                output: "out.gif".to_string(),
+                ruleset: "wireworld".to_string(),
+                pattern: None,
+                replay: None,
+                size: (20, 10),
+                generations: 5,
+                cell_size: 4,
+                grid_lines: true,
+                delay: 100,
+                seed: 0,
+            }
+        );
+
+        assert!(RenderArgs::parse(&args("--grid")).is_err());
+        assert!(RenderArgs::parse(&args("out.png --size 10")).is_err());
+        assert!(RenderArgs::parse(&args("out.png --generations")).is_err());
+        assert!(RenderArgs::parse(&args("out.png --frames 3")).is_err());
+        assert!(RenderArgs::parse(&args("a.png b.png")).is_err());
+
+        // Values which would overflow or exhaust memory are refused
+        assert!(RenderArgs::parse(&args("out.png --size 100000x100000")).is_err());
+        assert!(RenderArgs::parse(&args("out.png --cell-size 4294967296")).is_err());
+        assert!(RenderArgs::parse(&args("out.gif --delay 4294967296")).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::BUNDLED_RULESETS;
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub(crate) struct Spawn {
+    /// Width of the square the spray tool scatters cells over
+    pub spray_size: f32,
+    /// Chance of each cell under the spray becoming the spawn state
+    pub spray_density: f32,
+    pub timer_size: f32,
+    pub timer: f32,
+    pub spawn: bool,
+    pub spawn_state: usize,
+}
+
+impl Default for Spawn {
+    fn default() -> Self {
+        Self {
+            spray_size: 5.,
+            spray_density: 1. / 3.,
+            timer_size: 5.,
+            timer: 1.,
+            spawn: false,
+            spawn_state: 0,
+        }
+    }
+}
+
+pub(crate) struct Config {
+    pub spawn: Spawn,
+    pub ruleset: String,
+    pub paused: bool,
+    pub step_time: f32,
+    /// Generations to run each step, to go faster than one per frame
+    pub generations_per_step: f32,
+    /// Pixels per cell the camera starts at, taken from the camera when saving a snapshot
+    pub cell_size: f32,
+    /// Width and height of the world in cells, independent of the window
+    pub grid_size: (usize, usize),
+    /// What the random number generator was seeded with
+    pub seed: u64,
+    /// Periodic spawns made so far, which along with `seed` decides where the next one lands
+    pub spawns: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            spawn: Default::default(),
+            ruleset: BUNDLED_RULESETS[0].1.to_string(),
+            paused: false,
+            step_time: 0.5,
+            generations_per_step: 1.0,
+            cell_size: 10.0,
+            grid_size: (80, 60),
+            seed: 0,
+            spawns: 0,
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use std::mem::size_of;
+
+use crate::{
+    ruleset::Ruleset,
+    world::{Cell, World},
+};
+
+/// How many changes are kept to be undone
+const MAX_CHANGES: usize = 200;
+/// Most memory the kept changes take, so edits to big worlds keep fewer of them
+const MAX_BYTES: usize = 64 << 20;
+
+/// The cells a change affected, as indices into the history's palette
+enum Cells {
+    /// Each cell which changed, as (index, before, after)
+    Changed(Vec<(u32, u16, u16)>),
+    /// Every cell before and after, when the world changed size
+    Replaced(Vec<u16>, Vec<u16>),
+}
+
+impl Cells {
+    fn bytes(&self) -> usize {
+        match self {
+            Cells::Changed(changed) => changed.len() * size_of::<(u32, u16, u16)>(),
+            Cells::Replaced(before, after) => (before.len() + after.len()) * size_of::<u16>(),
+        }
+    }
+}
+
+/// A ruleset along with the JSON it was read from
+#[derive(Clone)]
+struct RulesetSource {
+    ruleset: Ruleset,
+    json: String,
+}
+
+/// One undoable edit of the world
+struct Change {
+    size: [(usize, usize); 2],
+    generation: [u64; 2],
+    /// Only kept when the change switched rulesets
+    rulesets: Option<Box<[RulesetSource; 2]>>,
+    cells: Cells,
+}
+
+/// The world as it was when an edit began
+struct Pending {
+    size: (usize, usize),
+    generation: u64,
+    revision: u64,
+    cells: Vec<u16>,
+    ruleset: Ruleset,
+}
+
+/// Undo and redo stacks of changes to the world, stored as the cells which differ
+///
+/// Changes only apply to the world exactly as it was left by the latest one, so once the
+/// world moves on in any other way, such as by running a generation, they are forgotten.
+pub(crate) struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+    pending: Option<Pending>,
+    /// Each distinct cell in a kept change, which changes index into
+    palette: Vec<Cell>,
+    /// Memory taken by every kept change's cells
+    bytes: usize,
+    max_bytes: usize,
+    /// The world revision left by the latest change, undo or redo
+    revision: Option<u64>,
+    /// JSON of the ruleset the world had after the latest change
+    json: String,
+}
+
+impl History {
+    pub fn new(json: &str) -> Self {
+        History {
+            undo: vec![],
+            redo: vec![],
+            pending: None,
+            palette: vec![],
+            bytes: 0,
+            max_bytes: MAX_BYTES,
+            revision: None,
+            json: json.to_string(),
+        }
+    }
+
+    /// Forgets every change. The palette is kept, as an edit in progress may index into it.
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.bytes = 0;
+    }
+
+    /// Forgets every change and edit in progress, when there are too many distinct cells to
+    /// index
+    fn give_up(&mut self) {
+        self.clear();
+        self.pending = None;
+        self.palette.clear();
+    }
+
+    /// The palette index of a cell, adding it to the palette if it is new
+    fn palette_index(&mut self, cell: &Cell) -> Option<u16> {
+        match self.palette.iter().position(|c| c == cell) {
+            Some(index) => Some(index as u16),
+            None if self.palette.len() <= u16::MAX as usize => {
+                self.palette.push(cell.clone());
+                Some((self.palette.len() - 1) as u16)
+            }
+            None => None,
+        }
+    }
+
+    /// Every cell as a palette index, or `None` if there are too many distinct cells to index
+    fn palette_indices(&mut self, cells: &[Cell]) -> Option<Vec<u16>> {
+        let mut indices = Vec::with_capacity(cells.len());
+        let mut last: Option<u16> = None;
+        for cell in cells {
+            // Neighbouring cells are usually the same, so try the last one first
+            let index = match last {
+                Some(index) if self.palette[index as usize] == *cell => index,
+                _ => self.palette_index(cell)?,
+            };
+            indices.push(index);
+            last = Some(index);
+        }
+        Some(indices)
+    }
+
+    /// Remembers the world as it is before an edit. Does nothing while an edit is already in
+    /// progress, so that a whole stroke of the mouse is undone together.
+    pub fn begin(&mut self, world: &World) {
+        if self.pending.is_some() {
+            return;
+        }
+        let Some(cells) = self.palette_indices(&world.cells) else {
+            self.give_up();
+            return;
+        };
+        self.pending = Some(Pending {
+            size: (world.width, world.height),
+            generation: world.generation,
+            revision: world.revision,
+            cells,
+            ruleset: world.ruleset.clone(),
+        });
+    }
+
+    /// Finishes the edit started by `begin`, recording it if it changed anything. `json` is
+    /// the source of the world's ruleset.
+    pub fn end(&mut self, world: &World, json: &str) {
+        let Some(before) = self.pending.take() else {
+            return;
+        };
+        // Older changes no longer apply if the world moved on before the edit began
+        if self.revision != Some(before.revision) {
+            self.clear();
+        }
+
+        let size = (world.width, world.height);
+        let cells = if before.size == size {
+            let mut changed = vec![];
+            for (i, (&before, after)) in before.cells.iter().zip(&world.cells).enumerate() {
+                if self.palette[before as usize] == *after {
+                    continue;
+                }
+                let Some(after) = self.palette_index(after) else {
+                    self.give_up();
+                    return;
+                };
+                changed.push((i as u32, before, after));
+            }
+            Cells::Changed(changed)
+        } else {
+            let Some(after) = self.palette_indices(&world.cells) else {
+                self.give_up();
+                return;
+            };
+            Cells::Replaced(before.cells, after)
+        };
+        let rulesets = (json != self.json).then(|| {
+            Box::new([
+                RulesetSource {
+                    ruleset: before.ruleset,
+                    json: self.json.clone(),
+                },
+                RulesetSource {
+                    ruleset: world.ruleset.clone(),
+                    json: json.to_string(),
+                },
+            ])
+        });
+
+        self.revision = Some(world.revision);
+        if matches!(&cells, Cells::Changed(changed) if changed.is_empty()) && rulesets.is_none() {
+            return;
+        }
+
+        self.json = json.to_string();
+        self.redo.clear();
+        self.bytes = self.undo.iter().map(|change| change.cells.bytes()).sum();
+        self.bytes += cells.bytes();
+        self.undo.push(Change {
+            size: [before.size, size],
+            generation: [before.generation, world.generation],
+            rulesets,
+            cells,
+        });
+        while self.undo.len() > MAX_CHANGES || self.bytes > self.max_bytes {
+            let oldest = self.undo.remove(0);
+            self.bytes -= oldest.cells.bytes();
+        }
+    }
+
+    /// Forgets every change if the world has moved on since the latest one
+    fn check_current(&mut self, world: &World) -> Result<(), String> {
+        if self.revision == Some(world.revision) {
+            return Ok(());
+        }
+        self.clear();
+        Err("The world has changed since the last edit, so it can't be undone".to_string())
+    }
+
+    /// Reverts the latest change, returning false if there is none, or an error if the world
+    /// has moved on since. `json` is set to the source of the restored ruleset if the change
+    /// switched rulesets.
+    pub fn undo(&mut self, world: &mut World, json: &mut String) -> Result<bool, String> {
+        self.pending = None;
+        if self.undo.is_empty() {
+            return Ok(false);
+        }
+        self.check_current(world)?;
+        let change = self.undo.pop().expect("Checked there is a change");
+        self.apply(&change, 0, world, json);
+        self.redo.push(change);
+        Ok(true)
+    }
+
+    /// Applies the latest undone change again, returning false if there is none, or an error
+    /// if the world has moved on since
+    pub fn redo(&mut self, world: &mut World, json: &mut String) -> Result<bool, String> {
+        self.pending = None;
+        if self.redo.is_empty() {
+            return Ok(false);
+        }
+        self.check_current(world)?;
+        let change = self.redo.pop().expect("Checked there is a change");
+        self.apply(&change, 1, world, json);
+        self.undo.push(change);
+        Ok(true)
+    }
+
+    /// Puts the world into the state before (0) or after (1) a change
+    fn apply(&mut self, change: &Change, side: usize, world: &mut World, json: &mut String) {
+        (world.width, world.height) = change.size[side];
+        world.generation = change.generation[side];
+        if let Some(rulesets) = &change.rulesets {
+            world.ruleset = rulesets[side].ruleset.clone();
+            self.json = rulesets[side].json.clone();
+            *json = self.json.clone();
+        }
+
+        match &change.cells {
+            Cells::Changed(changed) => {
+                for &(i, before, after) in changed {
+                    let index = if side == 0 { before } else { after };
+                    world.cells[i as usize] = self.palette[index as usize].clone();
+                }
+            }
+            Cells::Replaced(before, after) => {
+                world.cells = if side == 0 { before } else { after }
+                    .iter()
+                    .map(|&index| self.palette[index as usize].clone())
+                    .collect();
+            }
+        }
+        world.touch();
+        self.revision = Some(world.revision);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, world, LIFE, WIREWORLD};
+
+    #[test]
+    fn test_undo_and_redo_edits() {
+        let mut world = world(LIFE, (5, 5), None);
+        let mut history = History::new(LIFE);
+        let mut json = LIFE.to_string();
+        let alive = world.state_cell(1).unwrap();
+
+        // A stroke over several frames is one change
+        history.begin(&world);
+        world.set_cell(1, 1, alive.clone());
+        history.begin(&world);
+        world.set_cell(2, 1, alive.clone());
+        history.end(&world, LIFE);
+
+        history.begin(&world);
+        world.fill(0, 3, 5, 1, &alive);
+        history.end(&world, LIFE);
+        let filled = world.to_pattern();
+
+        // Edits which change nothing aren't recorded
+        history.begin(&world);
+        history.end(&world, LIFE);
+
+        assert_eq!(history.undo(&mut world, &mut json), Ok(true));
+        assert_eq!(world.live_bounds(), Some((1, 1, 2, 1)));
+        assert_eq!(history.undo(&mut world, &mut json), Ok(true));
+        assert_eq!(world.live_bounds(), None);
+        assert_eq!(history.undo(&mut world, &mut json), Ok(false));
+
+        assert_eq!(history.redo(&mut world, &mut json), Ok(true));
+        assert_eq!(history.redo(&mut world, &mut json), Ok(true));
+        assert_eq!(world.to_pattern(), filled);
+        assert_eq!(history.redo(&mut world, &mut json), Ok(false));
+
+        // A new change can't be followed by redoing older ones
+        history.undo(&mut world, &mut json).unwrap();
+        history.begin(&world);
+        world.reset();
+        history.end(&world, LIFE);
+        assert_eq!(history.redo(&mut world, &mut json), Ok(false));
+        assert_eq!(json, LIFE);
+    }
+
+    #[test]
+    fn test_undo_reset_generation() {
+        let mut world = world(LIFE, (5, 5), None);
+        let mut history = History::new(LIFE);
+        let mut json = LIFE.to_string();
+        world.randomise();
+        world.next_generation();
+        world.next_generation();
+        let before = world.cells.clone();
+
+        history.begin(&world);
+        world.reset();
+        history.end(&world, LIFE);
+
+        history.undo(&mut world, &mut json).unwrap();
+        assert_eq!(world.generation, 2);
+        assert_eq!(world.cells, before);
+        history.redo(&mut world, &mut json).unwrap();
+        assert_eq!(world.generation, 0);
+    }
+
+    #[test]
+    fn test_undo_ruleset_switch_and_resize() {
+        let mut world = world(LIFE, (4, 4), None);
+        let mut history = History::new(LIFE);
+        let mut json = LIFE.to_string();
+        world.set_cell(1, 1, world.state_cell(1).unwrap());
+        let revision = world.revision;
+
+        history.begin(&world);
+        world = test_utils::world(WIREWORLD, (6, 3), None);
+        history.end(&world, WIREWORLD);
+
+        assert_eq!(history.undo(&mut world, &mut json), Ok(true));
+        assert_eq!(json, LIFE);
+        assert_eq!((world.width, world.height), (4, 4));
+        assert_eq!(world.get_cell(1, 1).state, "alive");
+        assert_eq!(world.ruleset.default_state, "dead");
+        assert_ne!(world.revision, revision);
+
+        assert_eq!(history.redo(&mut world, &mut json), Ok(true));
+        assert_eq!(json, WIREWORLD);
+        assert_eq!((world.width, world.height), (6, 3));
+        assert_eq!(world.cells.len(), 18);
+        assert_eq!(world.ruleset.default_state, "empty");
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut world = world(LIFE, (MAX_CHANGES + 10, 1), None);
+        let mut history = History::new(LIFE);
+        let mut json = LIFE.to_string();
+        let alive = world.state_cell(1).unwrap();
+
+        for x in 0..MAX_CHANGES + 10 {
+            history.begin(&world);
+            world.set_cell(x as isize, 0, alive.clone());
+            history.end(&world, LIFE);
+        }
+
+        let mut undone = 0;
+        while history.undo(&mut world, &mut json) == Ok(true) {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_CHANGES);
+        assert_eq!(world.live_bounds(), Some((0, 0, 10, 1)));
+
+        // Big changes are dropped to stay within the memory budget
+        let mut world = test_utils::world(LIFE, (100, 100), None);
+        history = History::new(LIFE);
+        history.max_bytes = 100 * 100 * size_of::<u16>() * 2 * 3;
+        for size in 0..10 {
+            history.begin(&world);
+            world.resize((100, 100 + size % 2), (0, 0));
+            history.end(&world, LIFE);
+        }
+        assert!(history.bytes <= history.max_bytes);
+        let mut undone = 0;
+        while history.undo(&mut world, &mut json) == Ok(true) {
+            undone += 1;
+        }
+        assert_eq!(undone, 2);
+    }
+
+    #[test]
+    fn test_changes_are_forgotten_once_the_world_moves_on() {
+        let mut world = world(LIFE, (5, 5), None);
+        let mut history = History::new(LIFE);
+        let mut json = LIFE.to_string();
+
+        history.begin(&world);
+        world.fill(1, 2, 3, 1, &world.state_cell(1).unwrap());
+        history.end(&world, LIFE);
+        world.next_generation();
+
+        // Undoing would put back cells which generations have since changed
+        assert!(history.undo(&mut world, &mut json).is_err());
+        assert_eq!(history.undo(&mut world, &mut json), Ok(false));
+        assert_eq!(world.live_bounds(), Some((2, 1, 1, 3)));
+
+        // Edits made after the world moved on can still be undone
+        history.begin(&world);
+        world.reset();
+        history.end(&world, LIFE);
+        assert_eq!(history.undo(&mut world, &mut json), Ok(true));
+        assert_eq!(world.live_bounds(), Some((2, 1, 1, 3)));
+        assert_eq!(history.undo(&mut world, &mut json), Ok(false));
+    }
+}
@@ -0,0 +1,168 @@
+use macroquad::prelude::*;
+
+use crate::{
+    pattern::Pattern,
+    ruleset::{Ruleset, RulesetColour},
+};
+
+/// Colours to match pixels against, with the index into `Ruleset::state_names` of the state
+/// each one stands for
+pub(crate) type Palette = Vec<([u8; 4], usize)>;
+
+/// Matches pixels to the colours of the ruleset's states
+pub fn ruleset_palette(ruleset: &Ruleset) -> Palette {
+    ruleset
+        .state_names()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((ruleset.states.get(*name)?.colour.rgba(), i)))
+        .collect()
+}
+
+/// Reads an explicit palette such as `#ffff00=conductor, #0000ff=head`
+pub fn parse_palette(text: &str, ruleset: &Ruleset) -> Result<Palette, String> {
+    let names = ruleset.state_names();
+
+    text.split([',', '\n'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (colour, state) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Expected COLOUR=STATE, found {entry:?}"))?;
+            let hex = colour.trim();
+            let colour = RulesetColour::Hex(hex.to_string());
+            if !colour.is_valid() {
+                return Err(format!(
+                    "Invalid hex colour {hex:?}, expected #RRGGBB or #RRGGBBAA"
+                ));
+            }
+            let state = state.trim();
+            let index = names
+                .iter()
+                .position(|name| *name == state)
+                .ok_or_else(|| format!("Undefined state `{state}`"))?;
+            Ok((colour.rgba(), index))
+        })
+        .collect()
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    Image::from_file_with_format(bytes, Some(ImageFormat::Png))
+        .map_err(|e| format!("Could not decode PNG: {e}"))
+}
+
+/// Scales an image to `width` by `height` cells, giving each the state of the palette
+/// colour closest to its pixel
+pub fn seed_pattern(image: &Image, width: usize, height: usize, palette: &Palette) -> Pattern {
+    let mut pattern = Pattern::new(width, height);
+    if image.width == 0 || image.height == 0 || palette.is_empty() {
+        return pattern;
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let image_x = x * image.width as usize / width;
+            let image_y = y * image.height as usize / height;
+            let i = (image_y * image.width as usize + image_x) * 4;
+            let pixel = [
+                image.bytes[i],
+                image.bytes[i + 1],
+                image.bytes[i + 2],
+                image.bytes[i + 3],
+            ];
+            pattern.set(x, y, nearest(pixel, palette));
+        }
+    }
+
+    pattern
+}
+
+/// Compares colours after blending them onto black, so transparent and black pixels match
+/// either a transparent or a black state
+fn nearest(pixel: [u8; 4], palette: &Palette) -> usize {
+    let blend = |[r, g, b, a]: [u8; 4]| [r, g, b].map(|channel| channel as i32 * a as i32 / 255);
+    let pixel = blend(pixel);
+
+    palette
+        .iter()
+        .min_by_key(|(colour, _)| {
+            blend(*colour)
+                .iter()
+                .zip(pixel)
+                .map(|(a, b)| (a - b).pow(2))
+                .sum::<i32>()
+        })
+        .map_or(0, |(_, state)| *state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wireworld() -> Ruleset {
+        Ruleset::from_json(include_str!("../rulesets/wireworld.json")).unwrap()
+    }
+
+    fn image(width: u16, height: u16, pixels: &[[u8; 4]]) -> Image {
+        Image {
+            width,
+            height,
+            bytes: pixels.concat(),
+        }
+    }
+
+    #[test]
+    fn test_nearest_state_colour() {
+        let ruleset = wireworld();
+        let palette = ruleset_palette(&ruleset);
+        assert_eq!(
+            ruleset.state_names(),
+            vec!["empty", "head", "tail", "conductor"]
+        );
+
+        let image = image(
+            4,
+            1,
+            &[
+                [0, 0, 0, 255],
+                [255, 255, 0, 255],
+                [20, 10, 230, 255],
+                [250, 30, 20, 0],
+            ],
+        );
+        let pattern = seed_pattern(&image, 4, 1, &palette);
+
+        assert_eq!(pattern.cells, vec![0, 3, 1, 0]);
+    }
+
+    #[test]
+    fn test_scaling() {
+        let palette = ruleset_palette(&wireworld());
+        let image = image(2, 1, &[[255, 255, 0, 255], [255, 0, 0, 255]]);
+
+        let pattern = seed_pattern(&image, 4, 2, &palette);
+        assert_eq!(pattern.cells, vec![3, 3, 2, 2, 3, 3, 2, 2]);
+
+        let pattern = seed_pattern(&image, 1, 1, &palette);
+        assert_eq!(pattern.cells, vec![3]);
+    }
+
+    #[test]
+    fn test_explicit_palette() {
+        let ruleset = wireworld();
+        let palette = parse_palette("#ffffff=conductor, #00ff00 = head", &ruleset).unwrap();
+        assert_eq!(
+            palette,
+            vec![([255, 255, 255, 255], 3), ([0, 255, 0, 255], 1)]
+        );
+
+        let image = image(2, 1, &[[250, 250, 250, 255], [0, 200, 0, 255]]);
+        assert_eq!(seed_pattern(&image, 2, 1, &palette).cells, vec![3, 1]);
+
+        assert!(parse_palette("#fff=head", &ruleset).is_err());
+        assert!(parse_palette("#ffffff=zombie", &ruleset).is_err());
+        assert!(parse_palette("#ffffff", &ruleset).is_err());
+        assert!(decode_png(b"not a png").is_err());
+    }
+}
@@ -1,302 +1,238 @@
-use std::collections::HashMap;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use macroquad::{
+    miniquad,
     prelude::*,
-    rand::ChooseRandom,
     ui::{hash, root_ui, widgets::Window, Skin},
 };
 
-use crate::ruleset::{Ruleset, RulesetColour};
+use crate::{
+    analysis::RulesetWarning,
+    camera::Camera,
+    config::Config,
+    history::History,
+    pattern::{macrocell, plaintext, rle, Pattern, SparsePattern},
+    pattern_library::PatternEntry,
+    ruleset::{set_family_count, Ruleset},
+    ruleset_library::{LibraryEntry, RulesetEntry, RulesetWatcher},
+    runner::{Run, StopWhen},
+    selection::Selection,
+    snapshot::Snapshot,
+    stats::Stats,
+    timeline::Timeline,
+    tools::Tool,
+    validation::RulesetError,
+    world::World,
+    world_view::WorldView,
+};
 
+mod analysis;
+mod binary;
+mod camera;
+mod cli;
+mod config;
+mod history;
+mod image_import;
+mod pattern;
+mod pattern_library;
+mod render;
 mod rule_parsing;
 mod ruleset;
+mod ruleset_library;
+mod runner;
+mod selection;
+mod snapshot;
+mod stats;
+#[cfg(test)]
+mod test_utils;
+mod timeline;
+mod tools;
+mod validation;
+mod world;
+mod world_view;
 
-#[derive(Clone, Debug, PartialEq)]
-struct Cell {
-    state: String,
-    colour: Color,
-}
+static WORLD_COLOUR: Color = color_u8!(0, 0, 0, 0);
 
-impl From<RulesetColour> for Color {
-    fn from(value: RulesetColour) -> Self {
-        match value {
-            RulesetColour::Rgba(r, g, b, a) => color_u8!(r, g, b, a),
-            RulesetColour::Hex(s) => {
-                if s.len() == 9 && s.starts_with('#') {
-                    let r = u8::from_str_radix(&s[1..3], 16).unwrap_or(0);
-                    let g = u8::from_str_radix(&s[3..5], 16).unwrap_or(0);
-                    let b = u8::from_str_radix(&s[5..7], 16).unwrap_or(0);
-                    let a = u8::from_str_radix(&s[7..9], 16).unwrap_or(0);
-                    color_u8!(r, g, b, a)
-                } else {
-                    color_u8!(0, 0, 0, 0)
-                }
-            }
-        }
-    }
+static BUNDLED_RULESETS: [(&str, &str); 5] = [
+    (
+        "Game of Life",
+        include_str!("../rulesets/game_of_life.json"),
+    ),
+    ("Highlife", include_str!("../rulesets/highlife.json")),
+    ("Immigration", include_str!("../rulesets/immigration.json")),
+    ("Cyclic", include_str!("../rulesets/cyclic.json")),
+    ("Wireworld", include_str!("../rulesets/wireworld.json")),
+];
+
+fn get_family_counts(ruleset: &Ruleset) -> Vec<(String, f32, usize)> {
+    let mut family_counts: Vec<(String, f32, usize)> = ruleset
+        .families
+        .iter()
+        .map(|(name, family)| (name.clone(), family.count as f32, family.count))
+        .collect();
+    family_counts.sort_by(|a, b| a.0.cmp(&b.0));
+    family_counts
 }
 
-struct World {
-    width: usize,
-    height: usize,
-    cells: Vec<Cell>,
-    ruleset: Ruleset,
+/// Finds the ruleset an RLE rule such as "B3/S23" refers to
+fn find_ruleset(rulesets: &[RulesetEntry], rule: &str) -> Option<usize> {
+    let rule = rle::normalise_rule(rule);
+    rulesets.iter().position(|RulesetEntry { json, .. }| {
+        Ruleset::from_json(json)
+            .ok()
+            .and_then(|ruleset| ruleset.rle_rule)
+            .is_some_and(|r| rle::normalise_rule(&r) == rule)
+    })
 }
 
-impl World {
-    fn new((width, height): (usize, usize), ruleset: Ruleset) -> Option<Self> {
-        let default_state = ruleset.default_state.clone();
-        if let Some(state) = ruleset.states.get(&default_state) {
-            Some(World {
-                width,
-                height,
-                cells: vec![
-                    Cell {
-                        state: default_state,
-                        colour: state.colour.clone().into()
-                    };
-                    width * height
-                ],
-                ruleset,
-            })
-        } else {
-            println!("No States defined");
-            None
-        }
-    }
-
-    fn reset(&mut self) {
-        let default_state = self.ruleset.default_state.clone();
-        if let Some(state) = self.ruleset.states.get(&default_state) {
-            self.cells = vec![
-                Cell {
-                    state: default_state,
-                    colour: state.colour.clone().into()
-                };
-                self.width * self.height
-            ]
-        }
-    }
-
-    fn randomise(&mut self) {
-        let states: Vec<String> = self.ruleset.states.keys().cloned().collect();
-        for cell in &mut self.cells {
-            let name = states.choose().unwrap();
-            let state = self.ruleset.states.get(name).expect("Unreachable");
-            cell.state = name.clone();
-            cell.colour = state.colour.clone().into();
-        }
-    }
-
-    fn get_index(&self, x: isize, y: isize) -> usize {
-        let width = self.width as isize;
-        let height = self.height as isize;
-        let x = if x < 0 { width + x } else { x };
-        let x = if x >= width { x - width } else { x };
-        let y = if y < 0 { height + y } else { y };
-        let y = if y >= height { y - height } else { y };
-        (y * width + x) as usize
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
     }
 
-    fn get_cell(&self, x: isize, y: isize) -> &Cell {
-        &self.cells[self.get_index(x, y)]
-    }
-
-    fn set_cell(&mut self, x: isize, y: isize, cell: Cell) {
-        let index = self.get_index(x, y);
-        self.cells[index] = cell;
-    }
-
-    fn get_neighbourhood(&self, x: isize, y: isize) -> HashMap<String, usize> {
-        let mut neighbour_counts = HashMap::new();
-
-        for (dx, dy) in self.ruleset.neighbourhood.get_neighbours() {
-            let (a, b) = (x + dx, y + dy);
-            let cell = self.get_cell(a, b);
-
-            // TODO: get rid of this clone
-            neighbour_counts
-                .entry(cell.state.clone())
-                .and_modify(|v| *v += 1)
-                .or_insert(1);
-        }
-
-        neighbour_counts
+    let mut dirs = vec![PathBuf::from("rulesets")];
+    dirs.extend(ruleset_library::user_dir());
+    let (mut rulesets, problems) = ruleset_library::discover(&dirs);
+    for problem in problems {
+        eprintln!("Skipping ruleset {problem}");
     }
 
-    fn next_generation(&mut self) {
-        let mut new_generation = self.cells.clone();
-
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let (x, y) = (x as isize, y as isize);
-                let neighbour_counts = self.get_neighbourhood(x, y);
-                let current_cell = self.get_cell(x, y);
-
-                // TODO: Remove this clone
-                if let Some(rules) = self.ruleset.states.get(&current_cell.state) {
-                    if let Some(next) = rules.transition(&neighbour_counts) {
-                        let colour = next
-                            .paint
-                            .as_ref()
-                            .unwrap_or(&self.ruleset.states.get(&next.next).unwrap().colour);
-                        new_generation[self.get_index(x, y)] = Cell {
-                            state: next.next.clone(),
-                            colour: colour.clone().into(),
-                        };
-                        // TODO: can I remove these clones?
-                    }
-                } else {
-                    println!(
-                        "No state rules found in {:?} with ruleset {:?}",
-                        current_cell, self.ruleset
-                    );
-                }
-            }
-        }
-
-        self.cells = new_generation;
-    }
-
-    fn spawn_group(&mut self, x: isize, y: isize, size: isize, state: &str) {
-        let state_name = state.to_string();
-        let state_definition = self.ruleset.states.get(state);
-        if state_definition.is_none() {
-            println!("No {} defined", state);
-            return;
-        }
-
-        let state_colour = state_definition.unwrap().colour.clone();
-
-        if size == 0 {
-            return;
-        } else if size == 1 {
-            self.set_cell(
-                x,
-                y,
-                Cell {
-                    state: state_name,
-                    colour: state_colour.into(),
-                },
-            );
-            return;
-        }
-
-        for dx in 0..size {
-            for dy in 0..size {
-                let nx = x + dx - 1;
-                let ny = y + dy - 1;
-                if nx < (self.width as isize) && ny < (self.height as isize) {
-                    // 1 in 3 chance of spawning a cell
-                    if rand::rand() < (u32::MAX / 3) {
-                        self.set_cell(
-                            nx,
-                            ny,
-                            Cell {
-                                state: state_name.clone(),
-                                colour: state_colour.clone().into(),
-                            },
-                        );
-                    }
-                }
+    // A ruleset given on the command line is added to the list and selected
+    let mut selected = 0;
+    if let Some(path) = args.first() {
+        match RulesetEntry::load(Path::new(path)) {
+            Ok(entry) => selected = ruleset_library::add(&mut rulesets, entry),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
             }
         }
     }
-}
-
-static WORLD_COLOUR: Color = color_u8!(0, 0, 0, 0);
-
-struct Spawn {
-    interact_size: f32,
-    timer_size: f32,
-    timer: f32,
-    spawn: bool,
-    spawn_state: usize,
-}
 
-impl Default for Spawn {
-    fn default() -> Self {
-        Self {
-            interact_size: 1.,
-            timer_size: 5.,
-            timer: 1.,
-            spawn: false,
-            spawn_state: 0,
-        }
+    let mut dirs = vec![PathBuf::from("patterns")];
+    dirs.extend(ruleset_library::config_dir().map(|dir| dir.join("patterns")));
+    let (patterns, problems) = pattern_library::discover(&dirs);
+    for problem in problems {
+        eprintln!("Skipping pattern {problem}");
     }
-}
 
-static GAME_OF_LIFE_STATE_MACHINE: &str = include_str!("../rulesets/game_of_life.json");
-static HIGHLIFE_STATE_MACHINE: &str = include_str!("../rulesets/highlife.json");
-static WIREWORLD_STATE_MACHINE: &str = include_str!("../rulesets/wireworld.json");
-static IMMIGRATION_STATE_MACHINE: &str = include_str!("../rulesets/immigration.json");
-static CYCLIC_STATE_MACHINE: &str = include_str!("../rulesets/cyclic.json");
-
-struct Config {
-    spawn: Spawn,
-    ruleset: String,
-    paused: bool,
-    step_time: f32,
-    cell_size: f32,
+    macroquad::Window::new("Game of Life", run(rulesets, selected, patterns));
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            spawn: Default::default(),
-            ruleset: GAME_OF_LIFE_STATE_MACHINE.to_string(),
-            paused: false,
-            step_time: 0.5,
-            cell_size: 10.0,
-        }
-    }
-}
-
-impl Config {
-    fn get_grid_size(&self) -> (usize, usize) {
-        (
-            (screen_width() / self.cell_size) as usize,
-            (screen_height() / self.cell_size) as usize,
-        )
-    }
-
-    fn get_cell_display_offset(&self) -> (usize, usize) {
-        (
-            (self.cell_size * 0.8) as usize,
-            (self.cell_size * 0.1) as usize,
-        )
-    }
-}
-
-#[macroquad::main("Game of Life")]
-async fn main() {
+async fn run(rulesets: Vec<RulesetEntry>, selected: usize, patterns: Vec<PatternEntry>) {
     // Pseuo-random seed generator
-    let time = (get_time() * 100_000.0).powi(3) as u64;
-    rand::srand(time);
+    let mut config = Config {
+        seed: (get_time() * 100_000.0).powi(3) as u64,
+        ruleset: rulesets[selected].json.clone(),
+        ..Config::default()
+    };
+    rand::srand(config.seed);
+    let spray_rng = rand::RandGenerator::new();
+    spray_rng.srand(config.seed);
 
     let mut elapsed_frame: f32 = 0.0;
     let mut elapsed_spawn: f32 = 0.0;
 
     let mut show_config: bool = false;
 
-    let mut config = Config::default();
+    // L shows bundled and user patterns, by default only those made for the current ruleset
+    let mut show_library = false;
+    let mut library_all_rulesets = false;
+    let mut thumbnails: Vec<Texture2D> = vec![];
+    // The state colours the thumbnails were drawn with
+    let mut thumbnail_colours: Vec<[u8; 4]> = vec![];
 
-    let mut defined_rule_ui: usize = 0;
-    let mut previous_defined_rule_ui: usize = 0;
+    let ruleset_names: Vec<&str> = rulesets.iter().map(|r| r.name.as_str()).collect();
+    let mut defined_rule_ui: usize = selected;
+    let mut previous_defined_rule_ui: usize = selected;
 
-    let mut previous_cell_size: f32 = config.cell_size;
+    let mut camera = Camera::new(config.cell_size);
+    let mut last_mouse = Vec2::from(mouse_position());
+    let mut world_view = WorldView::new();
 
-    let ruleset: Ruleset = serde_json::from_str(&config.ruleset).unwrap();
+    // Grid size sliders only take effect when Resize is pressed
+    let mut grid_size_ui = (config.grid_size.0 as f32, config.grid_size.1 as f32);
+    let mut keep_centred = true;
+
+    let ruleset = Ruleset::from_json(&config.ruleset).expect("Default ruleset is valid");
     println!("\n\n {:?} \n\n", ruleset);
 
-    let mut states: Vec<String> = ruleset.states.keys().cloned().collect();
+    let mut states: Vec<String> = ruleset
+        .state_names()
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
     // combo boxes only take &[&str], precreate to avoid allocating this every frame
     let mut states_ref: Vec<&str> = states.iter().map(|s| s.as_str()).collect();
 
-    let mut world = World::new(config.get_grid_size(), ruleset).unwrap();
+    // (family name, slider value, count currently in the ruleset)
+    let mut family_counts = get_family_counts(&ruleset);
+
+    let mut world = World::new(config.grid_size, ruleset).unwrap();
     world.randomise();
 
     let mut reset_sim = false;
+    let mut refresh_states = false;
+
+    // Edits are recorded from just before they start until the mouse button is up
+    let mut history = History::new(&config.ruleset);
+
+    // Past generations to step back or scrub through
+    let mut timeline = Timeline::new();
+
+    // Runs until a number of generations pass or the world settles, then pauses
+    let mut run: Option<Run> = None;
+    let mut run_status = String::new();
+    let mut run_length: f32 = 100.0;
+    let mut step_once = false;
+
+    // Overlay of the generation, speed and populations, only sampled while shown
+    let mut stats = Stats::new();
+    let mut show_stats = false;
+
+    // The selected ruleset file is reloaded whenever it is saved
+    let mut watched_rule = selected;
+    let mut watcher = rulesets[selected].path.clone().map(RulesetWatcher::new);
+    let mut elapsed_watch: f32 = 0.0;
+    let mut reload_errors: Vec<RulesetError> = vec![];
+
+    let mut pattern_path = String::from("pattern.rle");
+    let mut pattern_position: (f32, f32) = (0.0, 0.0);
+    let mut pattern_status = String::new();
+    // Loaded patterns wait for any ruleset change they need before being placed
+    let mut pending_pattern: Option<Pattern> = None;
+
+    // Dragging selects a region to edit, and pasted patterns follow the mouse until placed
+    let mut selection: Option<Selection> = None;
+    let mut paste: Option<Pattern> = None;
+    // Texture of the pattern being pasted, and how many cells each texel covers
+    let mut paste_ghost: Option<(Texture2D, usize)> = None;
+    let mut selection_status = String::new();
+    // Chance of each cell becoming live when randomising the selection
+    let mut selection_density: f32 = 0.33;
+
+    let tool_names: Vec<&str> = Tool::ALL.iter().map(|t| t.name()).collect();
+    let mut tool_ui: usize = 0;
+    let mut tool = Tool::ALL[tool_ui];
+    // Where the current line or rectangle started, or the last cell the pencil drew
+    let mut drag_start: Option<(isize, isize)> = None;
+
+    let mut image_path = String::from("seed.png");
+    // Empty to match pixels to the ruleset's own state colours
+    let mut image_palette = String::new();
+    let mut image_status = String::new();
+
+    let mut snapshot_path = String::from("snapshot.json");
+    let mut snapshot_status = String::new();
+    // A loaded snapshot replaces the world the next time the simulation is reset
+    let mut pending_world: Option<World> = None;
+
+    let mut ruleset_errors: Vec<RulesetError> = vec![];
+    let mut ruleset_warnings: Vec<RulesetWarning> = world.ruleset.analyse();
 
     // UI Skins
     let white_text_style = root_ui()
@@ -307,88 +243,580 @@ async fn main() {
         label_style: white_text_style,
         ..root_ui().default_skin()
     };
+    let error_text_style = root_ui()
+        .style_builder()
+        .text_color(color_u8!(200, 0, 0, 255))
+        .build();
+    let error_text_skin = Skin {
+        label_style: error_text_style,
+        ..root_ui().default_skin()
+    };
+    let warning_text_style = root_ui()
+        .style_builder()
+        .text_color(color_u8!(190, 110, 0, 255))
+        .build();
+    let warning_text_skin = Skin {
+        label_style: warning_text_style,
+        ..root_ui().default_skin()
+    };
 
     loop {
-        // Only update the world if the game is an 'update frame'
+        if watched_rule != defined_rule_ui {
+            watched_rule = defined_rule_ui;
+            watcher = rulesets[defined_rule_ui]
+                .path
+                .clone()
+                .map(RulesetWatcher::new);
+            reload_errors.clear();
+        }
+
+        elapsed_watch += get_frame_time();
+        if elapsed_watch > 0.5 {
+            elapsed_watch = 0.0;
+            let reloaded = watcher.as_mut().and_then(|watcher| watcher.poll());
+            match reloaded.map(|json| {
+                let json = json.map_err(|e| vec![RulesetError::new("", e)])?;
+                Ruleset::from_json(&json).map(|ruleset| (json, ruleset))
+            }) {
+                Some(Ok((json, ruleset))) => {
+                    // Keep the grid, only cells in removed states are lost
+                    reload_errors.clear();
+                    pending_world = world.with_ruleset(ruleset);
+                    config.ruleset = json;
+                    reset_sim = true;
+                }
+                Some(Err(errors)) => {
+                    ruleset_errors = errors.clone();
+                    reload_errors = errors;
+                }
+                None => {}
+            }
+        }
+
+        // Switching rulesets and loading a pattern made for another ruleset are undone together
+        let replacing = reset_sim || pending_pattern.is_some();
+        if replacing {
+            history.begin(&world);
+        }
+
+        if reset_sim {
+            reset_sim = false;
+            match Ruleset::from_json(&config.ruleset) {
+                Ok(ok_ruleset) => {
+                    ruleset_errors.clear();
+                    let new_world = match pending_world.take() {
+                        Some(loaded) => Some(loaded),
+                        None => World::new(config.grid_size, ok_ruleset),
+                    };
+                    if let Some(new_world) = new_world {
+                        world = new_world;
+                        refresh_states = true;
+                    } else {
+                        println!("Error creating new world from ruleset")
+                    }
+                }
+                Err(errors) => ruleset_errors = errors,
+            }
+        }
+
+        if let Some(pattern) = pending_pattern.take() {
+            let (x, y) = (pattern_position.0 as isize, pattern_position.1 as isize);
+            pattern_status = match world.stamp(&pattern, x, y) {
+                Ok(()) => format!(
+                    "Placed {}x{} pattern at ({x}, {y})",
+                    pattern.width, pattern.height
+                ),
+                Err(e) => e,
+            };
+        }
+
+        if replacing {
+            history.end(&world, &config.ruleset);
+        }
+
+        // The world's ruleset changed, by switching or undoing a switch
+        if refresh_states {
+            refresh_states = false;
+            states = world
+                .ruleset
+                .state_names()
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            // combo boxes only take &[&str], precreate to avoid allocating this every frame
+            states_ref = states.iter().map(|s| s.as_str()).collect();
+            config.spawn.spawn_state = config.spawn.spawn_state.min(states.len() - 1);
+            family_counts = get_family_counts(&world.ruleset);
+            ruleset_warnings = world.ruleset.analyse();
+            timeline.clear();
+            paste_ghost = None;
+        }
+
+        // Only update the world if the game is an 'update frame'. Runs go as fast as they
+        // can, and single steps only happen while paused.
         elapsed_frame += get_frame_time();
-        if elapsed_frame > config.step_time && !config.paused {
+        let generations = if run.is_some() || (elapsed_frame > config.step_time && !config.paused) {
             elapsed_frame = 0.0;
+            config.generations_per_step as usize
+        } else if step_once {
+            1
+        } else {
+            0
+        };
+        step_once = false;
 
-            if reset_sim {
-                reset_sim = false;
-                let ruleset: Result<Ruleset, serde_json::Error> =
-                    serde_json::from_str(&config.ruleset);
-
-                match ruleset {
-                    Ok(ok_ruleset) => {
-                        states = ok_ruleset.states.keys().cloned().collect();
-                        // combo boxes only take &[&str], precreate to avoid allocating this every frame
-                        states_ref = states.iter().map(|s| s.as_str()).collect();
-                        if let Some(new_world) = World::new(config.get_grid_size(), ok_ruleset) {
-                            world = new_world;
-                        } else {
-                            println!("Error creating new world from ruleset")
+        if generations > 0 {
+            // Also records any edits made since the last generation
+            timeline.record(&world);
+        }
+        for _ in 0..generations {
+            world.next_generation();
+            // Only compares cells against the last frame and keeps the ones that changed, so
+            // recording every generation stays cheap when fast-forwarding
+            timeline.record(&world);
+
+            if let Some(reason) = run.as_mut().and_then(|run| run.check(&world)) {
+                run_status = reason;
+                run = None;
+                config.paused = true;
+                break;
+            }
+        }
+
+        // Camera: scroll to zoom at the cursor, right drag to pan and F to fit the live cells
+        let mouse = Vec2::from(mouse_position());
+        let screen_size = vec2(screen_width(), screen_height());
+        // The pattern library stays open over the world, so using it mustn't edit the world
+        let over_ui = show_library && root_ui().is_mouse_over(mouse);
+        if !show_config {
+            let wheel = mouse_wheel().1;
+            if wheel != 0.0 && !over_ui {
+                camera.zoom_at(mouse, 1.1f32.powf(wheel.signum()));
+            }
+            if is_mouse_button_down(MouseButton::Right) {
+                camera.pan(mouse - last_mouse);
+            }
+            if is_key_pressed(KeyCode::F) {
+                let (x, y, width, height) =
+                    world
+                        .live_bounds()
+                        .unwrap_or((0, 0, world.width, world.height));
+                camera.fit(
+                    Rect::new(x as f32, y as f32, width as f32, height as f32),
+                    screen_size,
+                );
+            }
+        }
+        last_mouse = mouse;
+
+        let mouse_cell = camera.cell_at(mouse);
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
+        if !show_config && paste.is_some() {
+            if is_mouse_button_pressed(MouseButton::Left) && !over_ui {
+                if let Some(pattern) = paste.take() {
+                    history.begin(&world);
+                    selection_status = match world.stamp(&pattern, mouse_cell.0, mouse_cell.1) {
+                        Ok(()) => format!("Pasted {}x{} pattern", pattern.width, pattern.height),
+                        Err(e) => e,
+                    };
+                }
+            } else if is_key_pressed(KeyCode::Escape) {
+                paste = None;
+                selection_status.clear();
+            }
+        } else if !show_config {
+            let (width, height) = (world.width as isize, world.height as isize);
+            let in_world =
+                |(x, y): (isize, isize)| (0..width).contains(&x) && (0..height).contains(&y);
+            let pressed = is_mouse_button_pressed(MouseButton::Left) && !over_ui;
+            let down = is_mouse_button_down(MouseButton::Left) && !over_ui;
+            if pressed && tool != Tool::Select {
+                history.begin(&world);
+            }
+
+            match tool {
+                Tool::Select => {
+                    // Selections stay inside the world
+                    let cell = (
+                        mouse_cell.0.clamp(0, world.width as isize - 1),
+                        mouse_cell.1.clamp(0, world.height as isize - 1),
+                    );
+                    if pressed {
+                        selection = Some(Selection::new(cell));
+                    } else if down {
+                        if let Some(selection) = &mut selection {
+                            selection.end = cell;
                         }
                     }
-                    Err(e) => println!("Ruleset error: {e}"),
                 }
+                Tool::Pencil | Tool::Eraser if down => {
+                    let index = if tool == Tool::Eraser {
+                        0
+                    } else {
+                        config.spawn.spawn_state
+                    };
+                    if let Some(cell) = world.state_cell(index) {
+                        // Join up with the last cell drawn so fast strokes have no gaps
+                        let from = drag_start.unwrap_or(mouse_cell);
+                        for position in tools::line(from, mouse_cell) {
+                            if in_world(position) {
+                                world.set_cell(position.0, position.1, cell.clone());
+                            }
+                        }
+                    }
+                    drag_start = Some(mouse_cell);
+                }
+                Tool::FloodFill if pressed => {
+                    if let Some(cell) = world.state_cell(config.spawn.spawn_state) {
+                        world.flood_fill(mouse_cell.0, mouse_cell.1, &cell);
+                    }
+                }
+                Tool::Spray if down && in_world(mouse_cell) => {
+                    world.spawn_group(
+                        mouse_cell.0,
+                        mouse_cell.1,
+                        config.spawn.spray_size as isize,
+                        &states[config.spawn.spawn_state],
+                        config.spawn.spray_density,
+                        &spray_rng,
+                    );
+                }
+                Tool::Line | Tool::Rectangle | Tool::FilledRectangle => {
+                    if pressed {
+                        drag_start = Some(mouse_cell);
+                    } else if is_mouse_button_released(MouseButton::Left) {
+                        let shape = drag_start
+                            .take()
+                            .and_then(|start| tool.shape(start, mouse_cell));
+                        if let (Some(shape), Some(cell)) =
+                            (shape, world.state_cell(config.spawn.spawn_state))
+                        {
+                            for position in shape.into_iter().filter(|&p| in_world(p)) {
+                                world.set_cell(position.0, position.1, cell.clone());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if !down {
+                drag_start = None;
             }
 
-            world.next_generation();
+            if is_key_pressed(KeyCode::Escape) {
+                selection = None;
+                selection_status.clear();
+            }
+
+            // Number keys pick tools in the order they are listed
+            for (i, key) in [
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                if is_key_pressed(key) {
+                    tool_ui = i;
+                }
+            }
         }
+        tool = Tool::ALL[tool_ui];
 
-        // Interactivity: click to add cells in a 5x5 square around the click
-        if !show_config && is_mouse_button_down(MouseButton::Left) {
-            let x = (mouse_position().0 / config.cell_size) as isize;
-            let y = (mouse_position().1 / config.cell_size) as isize;
+        // R rotates and H and V flip the pattern being pasted, or else the selected cells
+        let transform: Option<fn(&Pattern) -> Pattern> = if show_config || ctrl {
+            None
+        } else if is_key_pressed(KeyCode::R) {
+            Some(Pattern::rotated)
+        } else if is_key_pressed(KeyCode::H) {
+            Some(Pattern::flipped_horizontally)
+        } else if is_key_pressed(KeyCode::V) {
+            Some(Pattern::flipped_vertically)
+        } else {
+            None
+        };
+        if let Some(transform) = transform {
+            if let Some(pattern) = &mut paste {
+                *pattern = transform(pattern);
+                paste_ghost = None;
+            } else if let Some(current) = selection {
+                history.begin(&world);
+                match current.transform(&mut world, transform) {
+                    Ok(transformed) => selection = Some(transformed),
+                    Err(e) => selection_status = e,
+                }
+            }
+        }
 
-            world.spawn_group(
-                x,
-                y,
-                config.spawn.interact_size as isize,
-                &states[config.spawn.spawn_state],
-            );
+        if let (false, Some(current)) = (show_config, selection) {
+            let (x, y, width, height) = current.rect();
+            if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+                history.begin(&world);
+                current.clear(&mut world);
+                selection_status = format!("Cleared {width}x{height} cells");
+            }
+            if is_key_pressed(KeyCode::B) {
+                if let Some(cell) = world.state_cell(config.spawn.spawn_state) {
+                    history.begin(&world);
+                    world.fill(x, y, width, height, &cell);
+                    selection_status = format!(
+                        "Filled {width}x{height} cells with {}",
+                        states[config.spawn.spawn_state]
+                    );
+                }
+            }
+            if is_key_pressed(KeyCode::N) {
+                history.begin(&world);
+                world.scatter(x, y, width, height, selection_density);
+                selection_status = format!(
+                    "Randomised {width}x{height} cells at {:.0}% density",
+                    selection_density * 100.0
+                );
+            }
+        }
+
+        // Text fields in the config window take their own clipboard shortcuts
+        if !show_config && ctrl && (is_key_pressed(KeyCode::C) || is_key_pressed(KeyCode::X)) {
+            if let Some(current) = selection {
+                let (_, _, width, height) = current.rect();
+                let pattern = if is_key_pressed(KeyCode::X) {
+                    selection_status = format!("Cut {width}x{height} pattern");
+                    history.begin(&world);
+                    current.cut(&mut world)
+                } else {
+                    selection_status = format!("Copied {width}x{height} pattern");
+                    current.copy(&world)
+                };
+                let text = rle::write(
+                    &pattern,
+                    world.ruleset.rle_rule.as_deref(),
+                    world.ruleset.states.len(),
+                );
+                miniquad::window::clipboard_set(&text);
+            }
+        }
+
+        if !show_config && ctrl && (is_key_pressed(KeyCode::Z) || is_key_pressed(KeyCode::Y)) {
+            let previous_ruleset = config.ruleset.clone();
+            let changed = if is_key_pressed(KeyCode::Z) {
+                history.undo(&mut world, &mut config.ruleset)
+            } else {
+                history.redo(&mut world, &mut config.ruleset)
+            };
+            match changed {
+                Err(e) => selection_status = e,
+                Ok(false) => selection_status = "Nothing to undo or redo".to_string(),
+                Ok(true) if config.ruleset != previous_ruleset => {
+                    refresh_states = true;
+                    // Keep the UI from switching away from the restored ruleset
+                    if let Some(index) = rulesets.iter().position(|r| r.json == config.ruleset) {
+                        defined_rule_ui = index;
+                        previous_defined_rule_ui = index;
+                    }
+                }
+                Ok(true) => {}
+            }
+        }
+
+        if !show_config && ctrl && is_key_pressed(KeyCode::V) {
+            let pattern = miniquad::window::clipboard_get()
+                .ok_or_else(|| "Clipboard is empty".to_string())
+                .and_then(|text| pattern::parse(&text, world.width, world.height));
+            match pattern {
+                Ok(pattern) => {
+                    selection_status =
+                        "Click to place, R to rotate, H or V to flip, Escape to cancel".to_string();
+                    paste = Some(pattern);
+                    paste_ghost = None;
+                }
+                Err(e) => selection_status = e,
+            }
         }
 
         // Spawn some random cells
         elapsed_spawn += get_frame_time();
         if config.spawn.spawn && elapsed_spawn > config.spawn.timer && !config.paused {
             elapsed_spawn = 0.0;
-            let (width, height) = config.get_grid_size();
-            let x = rand::rand() as usize % width;
-            let y = rand::rand() as usize % height;
-            world.spawn_group(
-                x as isize,
-                y as isize,
+            world.spawn_random(
                 config.spawn.timer_size as isize,
                 &states[config.spawn.spawn_state],
+                // One in three, as when spraying with the default density
+                1.0 / 3.0,
+                config.seed,
+                config.spawns,
             );
+            config.spawns += 1;
         }
 
         // Clear the frame
 
         clear_background(WORLD_COLOUR);
 
-        // Render the world
+        // Render the part of the world the camera can see
+
+        world_view.draw(&world, &camera, WORLD_COLOUR);
+        let corner = camera.world_to_screen(Vec2::ZERO);
+        draw_rectangle_lines(
+            corner.x,
+            corner.y,
+            world.width as f32 * camera.zoom,
+            world.height as f32 * camera.zoom,
+            1.0,
+            DARKGRAY,
+        );
+
+        if let Some((x, y, width, height)) = selection.map(|s| s.rect()) {
+            let corner = camera.world_to_screen(vec2(x as f32, y as f32));
+            draw_rectangle_lines(
+                corner.x,
+                corner.y,
+                width as f32 * camera.zoom,
+                height as f32 * camera.zoom,
+                2.0,
+                WHITE,
+            );
+        }
 
-        for y in 0..world.height {
-            for x in 0..world.width {
-                let cell = world.get_cell(x as isize, y as isize);
-                let (display_size, offset) = config.get_cell_display_offset();
+        // Outline of the line or rectangle being dragged out, drawn a rectangle at a time
+        let cell = world.state_cell(config.spawn.spawn_state);
+        if let (Some(start), Some(cell)) = (drag_start, cell) {
+            let size = (world.width as isize, world.height as isize);
+            for (x, y, width, height) in tool.shape_rects(start, mouse_cell, size) {
+                let corner = camera.world_to_screen(vec2(x as f32, y as f32));
                 draw_rectangle(
-                    x as f32 * config.cell_size + offset as f32,
-                    y as f32 * config.cell_size + offset as f32,
-                    display_size as f32,
-                    display_size as f32,
-                    cell.colour,
+                    corner.x,
+                    corner.y,
+                    width as f32 * camera.zoom,
+                    height as f32 * camera.zoom,
+                    Color {
+                        a: cell.colour.a * 0.5,
+                        ..cell.colour
+                    },
                 );
             }
         }
 
+        if let (false, Some(watcher)) = (reload_errors.is_empty(), &watcher) {
+            let height = 32.0 + 20.0 * reload_errors.len() as f32;
+            draw_rectangle(
+                0.0,
+                0.0,
+                screen_width(),
+                height,
+                Color::new(0.0, 0.0, 0.0, 0.8),
+            );
+            draw_text(
+                &format!("Could not reload {}", watcher.path.display()),
+                8.0,
+                20.0,
+                20.0,
+                WHITE,
+            );
+            for (i, error) in reload_errors.iter().enumerate() {
+                draw_text(&error.to_string(), 8.0, 40.0 + 20.0 * i as f32, 20.0, RED);
+            }
+        }
+
+        // Ghost of the pattern being pasted, rebuilt only when the pattern or ruleset changes
+        if paste.is_none() {
+            paste_ghost = None;
+        }
+        if let Some(pattern) = paste.as_ref().filter(|_| paste_ghost.is_none()) {
+            paste_ghost = world_view::pattern_ghost(pattern, &world);
+        }
+        if let Some((texture, scale)) = &paste_ghost {
+            let corner = camera.world_to_screen(vec2(mouse_cell.0 as f32, mouse_cell.1 as f32));
+            draw_texture_ex(
+                texture,
+                corner.x,
+                corner.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(texture.size() * *scale as f32 * camera.zoom),
+                    ..Default::default()
+                },
+            );
+        }
+
+        if show_stats {
+            stats.record(&world, get_time());
+            stats.draw(&world, screen_width() - 8.0, 8.0);
+        }
+
+        if is_key_pressed(KeyCode::Tab) {
+            show_stats = !show_stats;
+        }
+
         if is_key_pressed(KeyCode::Q) {
             show_config = !show_config;
         }
 
+        if is_key_pressed(KeyCode::L) {
+            show_library = !show_library;
+        }
+
+        // Draw the pattern library, with thumbnails in the current ruleset's colours
+
+        if show_library {
+            let colours: Vec<[u8; 4]> = (0..world.ruleset.states.len())
+                .filter_map(|i| world.state_cell(i))
+                .map(|cell| cell.colour.into())
+                .collect();
+            if colours != thumbnail_colours {
+                thumbnails = patterns
+                    .iter()
+                    .map(|entry| {
+                        let (side, bytes) = pattern_library::thumbnail(&entry.pattern, &colours);
+                        let texture = Texture2D::from_rgba8(side as u16, side as u16, &bytes);
+                        texture.set_filter(FilterMode::Nearest);
+                        texture
+                    })
+                    .collect();
+                thumbnail_colours = colours;
+            }
+        }
+
+        if show_library
+            && !Window::new(
+                hash!(),
+                Vec2::new(10.0, 10.0),
+                Vec2::new(320.0, screen_height() * 0.8),
+            )
+            .label("Patterns")
+            .close_button(true)
+            .ui(&mut root_ui(), |ui| {
+                ui.label(None, "Click a pattern, then click to stamp it");
+                ui.label(None, "R rotates, H and V flip, Escape cancels");
+                ui.checkbox(hash!(), "All Rulesets", &mut library_all_rulesets);
+                ui.separator();
+
+                for (entry, thumbnail) in patterns.iter().zip(&thumbnails) {
+                    if !library_all_rulesets && !entry.suits(&world.ruleset) {
+                        continue;
+                    }
+                    let clicked = ui.texture(thumbnail.clone(), 48.0, 48.0);
+                    ui.same_line(60.0);
+                    if ui.button(None, entry.name.as_str()) || clicked {
+                        paste = Some(entry.pattern.clone());
+                        paste_ghost = None;
+                        selection_status = format!(
+                            "Click to place {}, R to rotate, H or V to flip, Escape to cancel",
+                            entry.name
+                        );
+                    }
+                }
+            })
+        {
+            show_library = false;
+        }
+
         if is_key_pressed(KeyCode::E) {
             config.spawn.spawn_state = (config.spawn.spawn_state + 1) % states.len();
         }
@@ -398,9 +826,26 @@ async fn main() {
         }
 
         if is_key_pressed(KeyCode::Space) {
-            config.paused = !config.paused;
+            if run.take().is_some() {
+                run_status = format!("Stopped at generation {}", world.generation);
+                config.paused = true;
+            } else {
+                config.paused = !config.paused;
+            }
+        }
+
+        // Arrow keys move the cursor in the config window's text fields while it is open
+        if !show_config && is_key_pressed(KeyCode::Left) && timeline.step_back(&mut world) {
+            config.paused = true;
+        }
+
+        if !show_config && is_key_pressed(KeyCode::Right) && config.paused {
+            step_once = true;
         }
 
+        // Follows the world unless dragged
+        let mut timeline_ui = world.generation as f32;
+
         // Draw config ui
 
         if show_config
@@ -414,17 +859,10 @@ async fn main() {
             .close_button(true)
             .ui(&mut root_ui(), |ui| {
                 ui.tree_node(hash!(), "Spawn", |tree_ui| {
-                    let spawn_size_range = 0f32..5f32;
-                    tree_ui.slider(
-                        hash!(),
-                        "Interact Size",
-                        spawn_size_range.clone(),
-                        &mut config.spawn.interact_size,
-                    );
                     tree_ui.slider(
                         hash!(),
                         "Periodic Spawn Size",
-                        spawn_size_range.clone(),
+                        0f32..5f32,
                         &mut config.spawn.timer_size,
                     );
                     tree_ui.slider(hash!(), "Spawn time", 0f32..10f32, &mut config.spawn.timer);
@@ -437,74 +875,398 @@ async fn main() {
                     );
                 });
 
-                config.spawn.interact_size = (config.spawn.interact_size as isize) as f32;
                 config.spawn.timer_size = (config.spawn.timer_size as isize) as f32;
 
                 ui.separator();
 
+                ui.tree_node(hash!(), "Tools", |tree_ui| {
+                    tree_ui.label(None, "Number keys 1 to 8 pick a tool");
+                    tree_ui.combo_box(hash!(), "Tool", &tool_names, &mut tool_ui);
+                    tree_ui.slider(
+                        hash!(),
+                        "Spray Size",
+                        1f32..10f32,
+                        &mut config.spawn.spray_size,
+                    );
+                    tree_ui.slider(
+                        hash!(),
+                        "Spray Density",
+                        0f32..1f32,
+                        &mut config.spawn.spray_density,
+                    );
+                });
+
+                config.spawn.spray_size = (config.spawn.spray_size as isize) as f32;
+
+                ui.separator();
+
+                ui.tree_node(hash!(), "Selection", |tree_ui| {
+                    tree_ui.label(None, "Select tool: Ctrl+C/X/V to copy, cut and paste");
+                    tree_ui.label(None, "R rotates, H and V flip, Delete clears");
+                    tree_ui.label(None, "B fills with the selected state, N randomises");
+                    tree_ui.slider(hash!(), "Density", 0f32..1f32, &mut selection_density);
+                });
+
+                ui.separator();
+
                 ui.tree_node(hash!(), "Rule Set", |tree_ui| {
                     tree_ui.combo_box(
                         hash!(),
                         "Defined Rules",
-                        &[
-                            "Game of Life",
-                            "Highlife",
-                            "Immigration",
-                            "Cyclic",
-                            "Wireworld",
-                        ],
+                        &ruleset_names,
                         &mut defined_rule_ui,
                     );
 
                     if defined_rule_ui != previous_defined_rule_ui {
-                        match defined_rule_ui {
-                            0 => config.ruleset = GAME_OF_LIFE_STATE_MACHINE.to_string(),
-                            1 => config.ruleset = HIGHLIFE_STATE_MACHINE.to_string(),
-                            2 => config.ruleset = IMMIGRATION_STATE_MACHINE.to_string(),
-                            3 => config.ruleset = CYCLIC_STATE_MACHINE.to_string(),
-                            4 => config.ruleset = WIREWORLD_STATE_MACHINE.to_string(),
-                            _ => unreachable!(),
-                        }
+                        // Files are read again in case they changed since startup
+                        let entry = &rulesets[defined_rule_ui];
+                        config.ruleset = entry
+                            .path
+                            .as_ref()
+                            .and_then(|path| fs::read_to_string(path).ok())
+                            .unwrap_or_else(|| entry.json.clone());
                         reset_sim = true;
                         previous_defined_rule_ui = defined_rule_ui;
                     }
 
+                    for (family, slider_count, count) in &mut family_counts {
+                        tree_ui.slider(
+                            hash!("family_count", family.as_str()),
+                            &format!("{family} count"),
+                            1f32..64f32,
+                            slider_count,
+                        );
+                        *slider_count = (*slider_count as usize) as f32;
+
+                        if *slider_count as usize != *count {
+                            *count = *slider_count as usize;
+                            match set_family_count(&config.ruleset, family, *count) {
+                                Ok(ruleset) => {
+                                    config.ruleset = ruleset;
+                                    reset_sim = true;
+                                }
+                                Err(e) => println!("Ruleset error: {e}"),
+                            }
+                        }
+                    }
+
                     tree_ui.label(None, "State Machine");
+
+                    tree_ui.push_skin(&error_text_skin);
+                    for error in &ruleset_errors {
+                        tree_ui.label(None, &error.to_string());
+                    }
+                    tree_ui.pop_skin();
+
+                    tree_ui.push_skin(&warning_text_skin);
+                    for warning in &ruleset_warnings {
+                        tree_ui.label(None, &format!("Warning: {warning}"));
+                    }
+                    tree_ui.pop_skin();
+
                     if tree_ui.editbox(
                         hash!(),
                         Vec2::new(screen_width() * 0.75, screen_height() * 0.75),
                         &mut config.ruleset,
                     ) {
-                        reset_sim = true;
+                        // Only restart with rulesets that are valid, otherwise show what is wrong
+                        match Ruleset::from_json(&config.ruleset) {
+                            Ok(_) => {
+                                ruleset_errors.clear();
+                                reset_sim = true;
+                            }
+                            Err(errors) => ruleset_errors = errors,
+                        }
                     };
                 });
 
                 ui.separator();
 
+                ui.tree_node(hash!(), "Pattern", |tree_ui| {
+                    tree_ui.input_text(hash!(), "Pattern File", &mut pattern_path);
+                    tree_ui.slider(
+                        hash!(),
+                        "Place X",
+                        0f32..world.width as f32,
+                        &mut pattern_position.0,
+                    );
+                    tree_ui.slider(
+                        hash!(),
+                        "Place Y",
+                        0f32..world.height as f32,
+                        &mut pattern_position.1,
+                    );
+                    pattern_position = (
+                        (pattern_position.0 as usize) as f32,
+                        (pattern_position.1 as usize) as f32,
+                    );
+
+                    if tree_ui.button(None, "Load") {
+                        let pattern = pattern::read_file(&pattern_path, world.width, world.height);
+
+                        match pattern {
+                            Ok(pattern) => {
+                                // Switch to the ruleset the pattern was made for
+                                let current_rule = world.ruleset.rle_rule.as_deref();
+                                let other_rule = pattern.rule.as_deref().filter(|rule| {
+                                    current_rule.map(rle::normalise_rule)
+                                        != Some(rle::normalise_rule(rule))
+                                });
+                                if let Some(index) =
+                                    other_rule.and_then(|rule| find_ruleset(&rulesets, rule))
+                                {
+                                    config.ruleset = rulesets[index].json.clone();
+                                    defined_rule_ui = index;
+                                    previous_defined_rule_ui = index;
+                                    reset_sim = true;
+                                }
+                                pending_pattern = Some(pattern);
+                            }
+                            Err(e) => pattern_status = e,
+                        }
+                    }
+
+                    if tree_ui.button(None, "Save") {
+                        let pattern = world.to_pattern();
+                        let text = if pattern_path.ends_with(".mc") {
+                            macrocell::write(
+                                &SparsePattern::from(&pattern),
+                                world.ruleset.rle_rule.as_deref(),
+                            )
+                        } else if pattern_path.ends_with(".cells") {
+                            plaintext::write(&pattern, None)
+                        } else {
+                            rle::write(
+                                &pattern,
+                                world.ruleset.rle_rule.as_deref(),
+                                world.ruleset.states.len(),
+                            )
+                        };
+                        pattern_status = match fs::write(&pattern_path, text) {
+                            Ok(()) => format!(
+                                "Saved {}x{} pattern to {pattern_path}",
+                                pattern.width, pattern.height
+                            ),
+                            Err(e) => format!("Could not write {pattern_path}: {e}"),
+                        };
+                    }
+
+                    if !pattern_status.is_empty() {
+                        tree_ui.label(None, &pattern_status);
+                    }
+                });
+
+                ui.separator();
+
+                ui.tree_node(hash!(), "Seed Image", |tree_ui| {
+                    tree_ui.input_text(hash!(), "PNG File", &mut image_path);
+                    tree_ui.input_text(hash!(), "Palette", &mut image_palette);
+                    tree_ui.label(None, "Palette entries look like #ffff00=conductor");
+
+                    if tree_ui.button(None, "Seed") {
+                        let palette = if image_palette.trim().is_empty() {
+                            Ok(image_import::ruleset_palette(&world.ruleset))
+                        } else {
+                            image_import::parse_palette(&image_palette, &world.ruleset)
+                        };
+                        let pattern = palette.and_then(|palette| {
+                            let bytes = fs::read(&image_path)
+                                .map_err(|e| format!("Could not read {image_path}: {e}"))?;
+                            let image = image_import::decode_png(&bytes)?;
+                            Ok(image_import::seed_pattern(
+                                &image,
+                                world.width,
+                                world.height,
+                                &palette,
+                            ))
+                        });
+
+                        image_status = match pattern {
+                            Ok(pattern) => {
+                                history.begin(&world);
+                                world.reset();
+                                match world.stamp(&pattern, 0, 0) {
+                                    Ok(()) => format!("Seeded the world from {image_path}"),
+                                    Err(e) => e,
+                                }
+                            }
+                            Err(e) => e,
+                        };
+                    }
+
+                    if !image_status.is_empty() {
+                        tree_ui.label(None, &image_status);
+                    }
+                });
+
+                ui.separator();
+
+                ui.tree_node(hash!(), "Snapshot", |tree_ui| {
+                    tree_ui.input_text(hash!(), "Snapshot File", &mut snapshot_path);
+
+                    if tree_ui.button(None, "Save") {
+                        // Snapshots start the camera at the zoom it was saved with
+                        config.cell_size = camera.zoom;
+                        snapshot_status = match Snapshot::capture(&world, &config)
+                            .save(&snapshot_path)
+                        {
+                            Ok(()) => {
+                                format!("Saved generation {} to {snapshot_path}", world.generation)
+                            }
+                            Err(e) => e,
+                        };
+                    }
+
+                    if tree_ui.button(None, "Load") {
+                        let loaded = Snapshot::load(&snapshot_path)
+                            .and_then(|snapshot| snapshot.restore(&mut config));
+                        match loaded {
+                            Ok(loaded) => {
+                                snapshot_status = format!(
+                                    "Loaded generation {} from {snapshot_path}",
+                                    loaded.generation
+                                );
+                                grid_size_ui =
+                                    (config.grid_size.0 as f32, config.grid_size.1 as f32);
+                                camera = Camera::new(config.cell_size);
+                                // Keep the UI from resetting the restored world
+                                if let Some(index) =
+                                    rulesets.iter().position(|r| r.json == config.ruleset)
+                                {
+                                    defined_rule_ui = index;
+                                    previous_defined_rule_ui = index;
+                                }
+                                pending_world = Some(loaded);
+                                reset_sim = true;
+                            }
+                            Err(e) => snapshot_status = e,
+                        }
+                    }
+
+                    if !snapshot_status.is_empty() {
+                        tree_ui.label(None, &snapshot_status);
+                    }
+                });
+
+                ui.separator();
+
                 ui.checkbox(hash!(), "Pause", &mut config.paused);
 
+                ui.checkbox(hash!(), "Show Stats (Tab)", &mut show_stats);
+
                 ui.slider(hash!(), "Step Time", 0f32..2f32, &mut config.step_time);
 
+                ui.tree_node(hash!(), "Run", |tree_ui| {
+                    tree_ui.label(None, "Right arrow steps a generation while paused");
+                    tree_ui.slider(
+                        hash!(),
+                        "Generations Per Step",
+                        1f32..1000f32,
+                        &mut config.generations_per_step,
+                    );
+                    config.generations_per_step = config.generations_per_step.floor().max(1.0);
+                    tree_ui.slider(hash!(), "Run Length", 1f32..10000f32, &mut run_length);
+                    run_length = run_length.floor().max(1.0);
+
+                    if tree_ui.button(None, "Step") {
+                        config.paused = true;
+                        step_once = true;
+                    }
+                    let stop = if tree_ui.button(None, "Run") {
+                        Some(StopWhen::Generations(run_length as u64))
+                    } else if tree_ui.button(None, "Until Stable") {
+                        Some(StopWhen::Stable)
+                    } else if tree_ui.button(None, "Until Periodic") {
+                        Some(StopWhen::Periodic)
+                    } else if tree_ui.button(None, "Until Empty") {
+                        Some(StopWhen::Empty)
+                    } else {
+                        None
+                    };
+                    if let Some(stop) = stop {
+                        run = Some(Run::new(stop, &world));
+                        run_status = "Running".to_string();
+                    }
+                    if run.is_some() && tree_ui.button(None, "Stop") {
+                        run = None;
+                        run_status = format!("Stopped at generation {}", world.generation);
+                        config.paused = true;
+                    }
+
+                    if !run_status.is_empty() {
+                        tree_ui.label(None, &run_status);
+                    }
+                });
+
+                ui.tree_node(hash!(), "Timeline", |tree_ui| {
+                    tree_ui.label(None, "Left arrow steps back a generation");
+                    match timeline.range() {
+                        Some((oldest, newest)) if oldest < newest => {
+                            tree_ui.slider(
+                                hash!(),
+                                "Generation",
+                                oldest as f32..newest as f32,
+                                &mut timeline_ui,
+                            );
+                            let generation = timeline_ui as u64;
+                            if generation != world.generation
+                                && timeline.restore(&mut world, generation)
+                            {
+                                config.paused = true;
+                            }
+                        }
+                        _ => tree_ui.label(None, "No past generations kept yet"),
+                    }
+                    if tree_ui.button(None, "Step Back") && timeline.step_back(&mut world) {
+                        config.paused = true;
+                    }
+                });
+
+                let mut zoom = camera.zoom;
                 ui.slider(
                     hash!(),
-                    "Cell Size",
-                    2f32..(f32::min(screen_width(), screen_height()) / 10.0),
-                    &mut config.cell_size,
+                    "Zoom",
+                    camera::MIN_ZOOM..camera::MAX_ZOOM,
+                    &mut zoom,
                 );
-                config.cell_size = (config.cell_size as usize) as f32;
-
-                if previous_cell_size != config.cell_size {
-                    previous_cell_size = config.cell_size;
-                    reset_sim = true;
+                if zoom != camera.zoom {
+                    camera.set_zoom(zoom, screen_size);
                 }
 
+                ui.tree_node(hash!(), "Grid", |tree_ui| {
+                    tree_ui.slider(hash!(), "Grid Width", 1f32..1024f32, &mut grid_size_ui.0);
+                    tree_ui.slider(hash!(), "Grid Height", 1f32..1024f32, &mut grid_size_ui.1);
+                    grid_size_ui = (
+                        (grid_size_ui.0 as usize).max(1) as f32,
+                        (grid_size_ui.1 as usize).max(1) as f32,
+                    );
+                    tree_ui.checkbox(hash!(), "Keep Centred", &mut keep_centred);
+
+                    if tree_ui.button(None, "Resize") {
+                        let size = (grid_size_ui.0 as usize, grid_size_ui.1 as usize);
+                        let offset = if keep_centred {
+                            (
+                                (size.0 as isize - world.width as isize) / 2,
+                                (size.1 as isize - world.height as isize) / 2,
+                            )
+                        } else {
+                            (0, 0)
+                        };
+                        history.begin(&world);
+                        world.resize(size, offset);
+                        config.grid_size = size;
+                    }
+                });
+
                 ui.separator();
 
                 if ui.button(None, "Reset") {
+                    history.begin(&world);
                     world.reset();
                 }
 
                 if ui.button(None, "Randomise") {
+                    history.begin(&world);
                     world.randomise();
                 }
             })
@@ -512,14 +1274,25 @@ async fn main() {
             show_config = false;
         }
 
+        if !is_mouse_button_down(MouseButton::Left) {
+            history.end(&world, &config.ruleset);
+        }
+
         root_ui().push_skin(&white_text_skin);
 
+        if !selection_status.is_empty() {
+            root_ui().label(Vec2::new(0.0, screen_height() - 48.0), &selection_status);
+        }
         if config.paused {
             root_ui().label(Vec2::new(0.0, screen_height() - 32.0), "Paused!");
         }
         root_ui().label(
             Vec2::new(0.0, screen_height() - 16.0),
-            &format!("Selected: {}", states[config.spawn.spawn_state]),
+            &format!(
+                "Selected: {}  Tool: {}",
+                states[config.spawn.spawn_state],
+                tool.name()
+            ),
         );
 
         root_ui().pop_skin();
@@ -0,0 +1,307 @@
+use std::{collections::HashMap, fs};
+
+pub(crate) mod life;
+pub(crate) mod macrocell;
+pub(crate) mod plaintext;
+pub(crate) mod rle;
+
+/// Largest width or height of a pattern read from a file
+pub const MAX_SIZE: usize = 1 << 16;
+/// Most cells a pattern read from a file can have, so a bad header can't exhaust memory
+pub const MAX_CELLS: usize = 1 << 24;
+
+/// Checks that a pattern read from a file is small enough to be made dense
+pub fn check_size(width: usize, height: usize) -> Result<(), String> {
+    match width.checked_mul(height) {
+        Some(cells) if width <= MAX_SIZE && height <= MAX_SIZE && cells <= MAX_CELLS => Ok(()),
+        _ => Err(format!(
+            "Pattern is too large at {width}x{height}, the most is {MAX_CELLS} cells"
+        )),
+    }
+}
+
+/// The pattern file formats which can be read
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Format {
+    Rle,
+    Plaintext,
+    Life105,
+    Life106,
+    Macrocell,
+}
+
+impl Format {
+    /// Works out the format of a pattern file from its contents
+    pub fn detect(text: &str) -> Format {
+        let first_line = text.lines().map(str::trim).find(|line| !line.is_empty());
+
+        match first_line {
+            Some(line) if line.starts_with("#Life 1.05") => Format::Life105,
+            Some(line) if line.starts_with("#Life 1.06") => Format::Life106,
+            Some(line) if line.starts_with("[M2]") => Format::Macrocell,
+            Some(line)
+                if line.starts_with('!') || line.chars().all(|c| matches!(c, '.' | 'O' | '*')) =>
+            {
+                Format::Plaintext
+            }
+            _ => Format::Rle,
+        }
+    }
+}
+
+/// Reads a pattern in any supported format
+///
+/// Macrocell patterns are cropped to `width` by `height` as they are expanded, so huge ones
+/// are never made dense. Other formats are read whole.
+pub fn parse(text: &str, width: usize, height: usize) -> Result<Pattern, String> {
+    match Format::detect(text) {
+        Format::Rle => rle::parse(text),
+        Format::Plaintext => plaintext::parse(text),
+        Format::Life105 => life::parse_105(text),
+        Format::Life106 => life::parse_106(text),
+        Format::Macrocell => {
+            let macrocell = macrocell::Macrocell::read(text.as_bytes())?;
+            Ok(macrocell.crop(width, height))
+        }
+    }
+}
+
+/// Reads a pattern file in any supported format, cropping Macrocell patterns as `parse` does
+pub fn read_file(path: &str, width: usize, height: usize) -> Result<Pattern, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+    parse(&text, width, height)
+}
+
+/// A rectangle of cells which can be placed into a `World`
+///
+/// Cells are stored as indices into `Ruleset::state_names`, so 0 is always the default state.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Pattern {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<usize>,
+    /// The rule named by the file the pattern was read from, if any
+    pub rule: Option<String>,
+}
+
+impl Pattern {
+    pub fn new(width: usize, height: usize) -> Self {
+        Pattern {
+            width,
+            height,
+            cells: vec![0; width * height],
+            rule: None,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> usize {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, state: usize) {
+        self.cells[y * self.width + x] = state;
+    }
+
+    /// The highest state index used by the pattern
+    pub fn max_state(&self) -> usize {
+        self.cells.iter().copied().max().unwrap_or(0)
+    }
+
+    /// A copy turned 90° clockwise
+    pub fn rotated(&self) -> Pattern {
+        let mut rotated = Pattern {
+            rule: self.rule.clone(),
+            ..Pattern::new(self.height, self.width)
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                rotated.set(self.height - 1 - y, x, self.get(x, y));
+            }
+        }
+        rotated
+    }
+
+    /// A copy mirrored left to right
+    pub fn flipped_horizontally(&self) -> Pattern {
+        let mut flipped = self.clone();
+        for row in flipped.cells.chunks_mut(self.width.max(1)) {
+            row.reverse();
+        }
+        flipped
+    }
+
+    /// A copy mirrored top to bottom
+    pub fn flipped_vertically(&self) -> Pattern {
+        let mut flipped = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                flipped.set(x, self.height - 1 - y, self.get(x, y));
+            }
+        }
+        flipped
+    }
+}
+
+/// A pattern of unbounded size which only stores its non-default cells
+#[derive(Clone, PartialEq, Default, Debug)]
+pub(crate) struct SparsePattern {
+    pub cells: HashMap<(i64, i64), usize>,
+    pub rule: Option<String>,
+}
+
+impl SparsePattern {
+    pub fn get(&self, x: i64, y: i64) -> usize {
+        self.cells.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, state: usize) {
+        if state == 0 {
+            self.cells.remove(&(x, y));
+        } else {
+            self.cells.insert((x, y), state);
+        }
+    }
+
+    /// The smallest rectangle containing every cell, as (min x, min y, max x, max y)
+    pub fn bounds(&self) -> Option<(i64, i64, i64, i64)> {
+        self.cells
+            .keys()
+            .fold(None, |bounds, &(x, y)| match bounds {
+                None => Some((x, y, x, y)),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+                }
+            })
+    }
+
+    /// The highest state index used by the pattern
+    pub fn max_state(&self) -> usize {
+        self.cells.values().copied().max().unwrap_or(0)
+    }
+
+    /// The width and height of the pattern's bounds, saturating at `usize::MAX`
+    pub fn size(&self) -> (usize, usize) {
+        let Some((min_x, min_y, max_x, max_y)) = self.bounds() else {
+            return (0, 0);
+        };
+        let extent = |min: i64, max: i64| {
+            usize::try_from(max.abs_diff(min))
+                .unwrap_or(usize::MAX)
+                .saturating_add(1)
+        };
+        (extent(min_x, max_x), extent(min_y, max_y))
+    }
+
+    /// Copies at most `width` by `height` cells from the top left of the pattern's bounds
+    pub fn crop(&self, width: usize, height: usize) -> Pattern {
+        let Some((min_x, min_y, _, _)) = self.bounds() else {
+            return Pattern {
+                rule: self.rule.clone(),
+                ..Pattern::new(0, 0)
+            };
+        };
+
+        let (full_width, full_height) = self.size();
+        let width = width.min(full_width);
+        let height = height.min(full_height);
+        let mut pattern = Pattern::new(width, height);
+        pattern.rule = self.rule.clone();
+        for y in 0..height {
+            for x in 0..width {
+                pattern.set(x, y, self.get(min_x + x as i64, min_y + y as i64));
+            }
+        }
+        pattern
+    }
+}
+
+impl From<&Pattern> for SparsePattern {
+    fn from(pattern: &Pattern) -> Self {
+        let mut sparse = SparsePattern {
+            rule: pattern.rule.clone(),
+            ..SparsePattern::default()
+        };
+        for y in 0..pattern.height {
+            for x in 0..pattern.width {
+                sparse.set(x as i64, y as i64, pattern.get(x, y));
+            }
+        }
+        sparse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Format::detect("#N Glider\nx = 3, y = 3\nbo$2bo$3o!"),
+            Format::Rle
+        );
+        assert_eq!(Format::detect("x = 1, y = 1\no!"), Format::Rle);
+        assert_eq!(Format::detect("!Name: Glider\n.O"), Format::Plaintext);
+        assert_eq!(Format::detect("\n.O\n..O\nOOO"), Format::Plaintext);
+        assert_eq!(Format::detect("#Life 1.05\n*"), Format::Life105);
+        assert_eq!(Format::detect("#Life 1.06\n0 0"), Format::Life106);
+        assert_eq!(
+            Format::detect("[M2] (golly 4.0)\n1 0 1 0 1"),
+            Format::Macrocell
+        );
+    }
+
+    #[test]
+    fn test_formats_agree() {
+        let glider = [
+            "x = 3, y = 3\nbo$2bo$3o!",
+            "!Name: Glider\n.O\n..O\nOOO",
+            "#Life 1.05\n#P 0 0\n.*\n..*\n***",
+            "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2",
+            "[M2]\n.*$..*$***$",
+        ]
+        .map(|text| parse(text, 3, 3).unwrap().cells);
+
+        assert!(glider.iter().all(|cells| *cells == glider[0]));
+    }
+
+    #[test]
+    fn test_transforms() {
+        // .O
+        // ..
+        // OO
+        let mut pattern = Pattern::new(2, 3);
+        pattern.set(1, 0, 1);
+        pattern.set(0, 2, 1);
+        pattern.set(1, 2, 2);
+
+        let rotated = pattern.rotated();
+        assert_eq!((rotated.width, rotated.height), (3, 2));
+        assert_eq!(rotated.cells, vec![1, 0, 0, 2, 0, 1]);
+        assert_eq!(rotated.rotated().rotated().rotated(), pattern);
+
+        assert_eq!(pattern.flipped_horizontally().cells, vec![1, 0, 0, 0, 2, 1]);
+        assert_eq!(pattern.flipped_vertically().cells, vec![1, 2, 0, 0, 0, 1]);
+        assert_eq!(pattern.flipped_vertically().flipped_vertically(), pattern);
+    }
+
+    #[test]
+    fn test_sparse_crop() {
+        let mut sparse = SparsePattern::default();
+        sparse.set(-10, 5, 1);
+        sparse.set(-8, 5, 2);
+        sparse.set(-5, 6, 1);
+        sparse.set(-10, 50, 1);
+
+        let pattern = sparse.crop(4, 4);
+        assert_eq!((pattern.width, pattern.height), (4, 4));
+        assert_eq!(pattern.get(0, 0), 1);
+        assert_eq!(pattern.get(2, 0), 2);
+        assert_eq!(pattern.max_state(), 2);
+
+        assert_eq!(sparse.size(), (6, 46));
+        let whole = SparsePattern::from(&sparse.crop(100, 100));
+        assert_eq!(whole.cells.len(), 4);
+        assert_eq!(whole.get(0, 45), 1);
+    }
+}
@@ -0,0 +1,150 @@
+/*
+Life 1.05 and Life 1.06 patterns
+
+Life 1.06 lists the coordinates of each live cell after its header:
+
+#Life 1.06
+0 -1
+1 0
+
+Life 1.05 is made of blocks of plaintext-like rows, `.` dead and `*` alive, each placed at
+the position given by the `#P` line before it. `#R` gives the rule in S/B form:
+
+#Life 1.05
+#D Description
+#R 23/3
+#P -1 -1
+.*
+..*
+***
+*/
+
+use super::{check_size, Pattern, SparsePattern};
+
+pub fn parse_106(text: &str) -> Result<Pattern, String> {
+    let mut cells = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let coordinates: Vec<&str> = line.split_whitespace().collect();
+        let parse = |s: &str| {
+            s.parse::<i64>()
+                .map_err(|_| format!("Invalid coordinate {s:?} on line {}", i + 1))
+        };
+        match coordinates[..] {
+            [x, y] => cells.push((parse(x)?, parse(y)?)),
+            _ => return Err(format!("Expected \"x y\" on line {}", i + 1)),
+        }
+    }
+
+    from_cells(&cells, None)
+}
+
+pub fn parse_105(text: &str) -> Result<Pattern, String> {
+    let mut cells = vec![];
+    let mut rule = None;
+    let (mut block_x, mut y) = (0, 0);
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(position) = line.strip_prefix("#P") {
+            let coordinates: Vec<i64> = position
+                .split_whitespace()
+                .map(|s| s.parse::<i64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("Invalid #P line {}", i + 1))?;
+            match coordinates[..] {
+                [x, block_y] => (block_x, y) = (x, block_y),
+                _ => return Err(format!("Expected \"#P x y\" on line {}", i + 1)),
+            }
+        } else if let Some(r) = line.strip_prefix("#R") {
+            rule = Some(r.trim().to_string());
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    '.' => {}
+                    '*' | 'O' => cells.push((block_x.saturating_add(x as i64), y)),
+                    _ => {
+                        return Err(format!(
+                            "Unexpected character {c:?} at line {}, column {}",
+                            i + 1,
+                            x + 1
+                        ))
+                    }
+                }
+            }
+            y = y.saturating_add(1);
+        }
+    }
+
+    from_cells(&cells, rule)
+}
+
+/// Builds the smallest pattern containing every live cell, refusing ones too large to hold
+fn from_cells(cells: &[(i64, i64)], rule: Option<String>) -> Result<Pattern, String> {
+    let mut sparse = SparsePattern {
+        rule,
+        ..SparsePattern::default()
+    };
+    for &(x, y) in cells {
+        sparse.set(x, y, 1);
+    }
+
+    let (width, height) = sparse.size();
+    check_size(width, height)?;
+    Ok(sparse.crop(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_106() {
+        let pattern = parse_106("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n").unwrap();
+
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, vec![0, 1, 0, 0, 0, 1, 1, 1, 1]);
+        assert!(parse_106("#Life 1.06\n0\n").is_err());
+        assert!(parse_106("#Life 1.06\n0 a\n").is_err());
+
+        // Far apart cells would need a huge pattern
+        let far = "#Life 1.06\n-1099511627776 0\n1099511627776 0\n";
+        assert!(parse_106(far).unwrap_err().contains("too large"));
+        let extreme = "#Life 1.06\n-9223372036854775808 0\n9223372036854775807 0\n";
+        assert!(parse_106(extreme).is_err());
+    }
+
+    #[test]
+    fn test_parse_105() {
+        let text = "#Life 1.05
+#D Two gliders
+#R 23/3
+#P -1 -1
+.*
+..*
+***
+#P 4 3
+*
+";
+        let pattern = parse_105(text).unwrap();
+
+        assert_eq!((pattern.width, pattern.height), (6, 5));
+        assert_eq!(pattern.rule.as_deref(), Some("23/3"));
+        assert_eq!(pattern.get(1, 0), 1);
+        assert_eq!(pattern.get(0, 2), 1);
+        assert_eq!(pattern.get(5, 4), 1);
+        assert_eq!(pattern.cells.iter().filter(|c| **c == 1).count(), 6);
+        assert!(parse_105("#Life 1.05\n#P 1\n*").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+/*
+Macrocell patterns, with the .mc extension
+
+[M2] (golly 4.0)
+#R B3/S23
+.*$..*$***$
+4 1 0 0 0
+
+After the header, each line defines a quadtree node, numbered from 1 in file order. Lines
+starting with `.`, `*` or `$` are 8x8 leaves for two-state rules, with `$` ending each row.
+Other lines are `level nw ne sw se`, where the children are earlier nodes one level down,
+or 0 for an empty node. For multi-state rules level 1 nodes are 2x2 leaves whose "children"
+are cell states. The last node is the root, centred on the origin.
+
+Identical subtrees are shared, so huge regular patterns stay small. Nodes are read one line
+at a time, and cropping skips every subtree outside the cropped area, so a pattern with
+billions of live cells only costs as much as the part of it which is kept.
+*/
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use super::{Pattern, SparsePattern};
+
+/// The deepest tree whose coordinates fit in an `i64`
+const MAX_LEVEL: u32 = 62;
+
+/// A rectangle of cells as (min x, min y, max x, max y), inclusive
+type Bounds = (i64, i64, i64, i64);
+
+enum Node {
+    /// Live cells of a leaf, relative to its top left corner
+    Leaf(Vec<(i64, i64, usize)>),
+    Branch([usize; 4]),
+}
+
+/// The node table of a Macrocell file
+pub(crate) struct Macrocell {
+    nodes: Vec<(u32, Node)>,
+    pub rule: Option<String>,
+}
+
+impl Macrocell {
+    pub fn read(reader: impl BufRead) -> Result<Macrocell, String> {
+        let mut macrocell = Macrocell {
+            nodes: vec![],
+            rule: None,
+        };
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("Could not read line {}: {e}", i + 1))?;
+            let line = line.trim();
+
+            if i == 0 {
+                if !line.starts_with("[M2]") {
+                    return Err("Macrocell files must start with [M2]".to_string());
+                }
+            } else if let Some(rule) = line.strip_prefix("#R") {
+                macrocell.rule = Some(rule.trim().to_string());
+            } else if line.is_empty() || line.starts_with('#') {
+                continue;
+            } else if line.starts_with(['.', '*', '$']) {
+                let leaf = parse_leaf(line).map_err(|e| format!("{e} on line {}", i + 1))?;
+                macrocell.nodes.push((3, leaf));
+            } else {
+                let node = macrocell
+                    .parse_node(line)
+                    .map_err(|e| format!("{e} on line {}", i + 1))?;
+                macrocell.nodes.push(node);
+            }
+        }
+
+        Ok(macrocell)
+    }
+
+    fn parse_node(&self, line: &str) -> Result<(u32, Node), String> {
+        let numbers: Vec<usize> = line
+            .split_whitespace()
+            .map(|s| s.parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid node {line:?}"))?;
+
+        let [level, nw, ne, sw, se] = numbers[..] else {
+            return Err(format!("Expected \"level nw ne sw se\", found {line:?}"));
+        };
+        let level = level as u32;
+
+        if level == 0 || level > MAX_LEVEL {
+            return Err(format!("Invalid level {level}"));
+        }
+
+        if level == 1 {
+            let cells = [(0, 0, nw), (1, 0, ne), (0, 1, sw), (1, 1, se)]
+                .into_iter()
+                .filter(|(_, _, state)| *state != 0)
+                .collect();
+            return Ok((1, Node::Leaf(cells)));
+        }
+
+        for child in [nw, ne, sw, se] {
+            match child.checked_sub(1).map(|c| self.nodes.get(c)) {
+                None => {}
+                Some(Some((child_level, _))) if *child_level == level - 1 => {}
+                Some(Some(_)) => return Err(format!("Node {child} is not at level {}", level - 1)),
+                Some(None) => return Err(format!("Node {child} is not defined yet")),
+            }
+        }
+
+        Ok((level, Node::Branch([nw, ne, sw, se])))
+    }
+
+    /// Copies at most `width` by `height` cells from the top left of the pattern's bounds,
+    /// without visiting any node which lies outside them
+    pub fn crop(&self, width: usize, height: usize) -> Pattern {
+        let node_bounds = self.node_bounds();
+        let Some(Some((min_x, min_y, max_x, max_y))) = node_bounds.last().copied() else {
+            return Pattern {
+                rule: self.rule.clone(),
+                ..Pattern::new(0, 0)
+            };
+        };
+
+        // Positions are relative to the root's top left corner, so never overflow
+        let width = width.min((max_x - min_x + 1) as usize);
+        let height = height.min((max_y - min_y + 1) as usize);
+        let window = (
+            min_x,
+            min_y,
+            min_x + width as i64 - 1,
+            min_y + height as i64 - 1,
+        );
+
+        let mut pattern = Pattern::new(width, height);
+        pattern.rule = self.rule.clone();
+        self.visit(
+            &node_bounds,
+            self.nodes.len(),
+            0,
+            0,
+            window,
+            &mut |x, y, state| pattern.set((x - min_x) as usize, (y - min_y) as usize, state),
+        );
+        pattern
+    }
+
+    /// The live cells' bounds within each node, relative to its top left corner, or `None`
+    /// for nodes with no live cells
+    fn node_bounds(&self) -> Vec<Option<Bounds>> {
+        let union = |a: Bounds, b: Bounds| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3));
+
+        let mut bounds: Vec<Option<Bounds>> = Vec::with_capacity(self.nodes.len());
+        for (level, node) in &self.nodes {
+            let node_bounds = match node {
+                Node::Leaf(cells) => cells.iter().map(|&(x, y, _)| (x, y, x, y)).reduce(union),
+                Node::Branch(children) => {
+                    let half = 1 << (level - 1);
+                    let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+                    children
+                        .iter()
+                        .zip(offsets)
+                        .filter_map(|(child, (dx, dy))| {
+                            let (min_x, min_y, max_x, max_y) =
+                                (*bounds.get(child.checked_sub(1)?)?)?;
+                            Some((min_x + dx, min_y + dy, max_x + dx, max_y + dy))
+                        })
+                        .reduce(union)
+                }
+            };
+            bounds.push(node_bounds);
+        }
+        bounds
+    }
+
+    /// Calls `f` with the position and state of every live cell of a node inside `window`,
+    /// with the node's top left corner at (x, y)
+    fn visit(
+        &self,
+        node_bounds: &[Option<Bounds>],
+        index: usize,
+        x: i64,
+        y: i64,
+        window: Bounds,
+        f: &mut impl FnMut(i64, i64, usize),
+    ) {
+        let Some((min_x, min_y, max_x, max_y)) = node_bounds[index - 1] else {
+            return;
+        };
+        let (left, top, right, bottom) = window;
+        if x + max_x < left || x + min_x > right || y + max_y < top || y + min_y > bottom {
+            return;
+        }
+
+        let (level, node) = &self.nodes[index - 1];
+        match node {
+            Node::Leaf(cells) => {
+                for (dx, dy, state) in cells {
+                    let (x, y) = (x + dx, y + dy);
+                    if (left..=right).contains(&x) && (top..=bottom).contains(&y) {
+                        f(x, y, *state);
+                    }
+                }
+            }
+            Node::Branch(children) => {
+                let half = 1 << (level - 1);
+                let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+                for (child, (dx, dy)) in children.iter().zip(offsets) {
+                    if *child != 0 {
+                        self.visit(node_bounds, *child, x + dx, y + dy, window, f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_leaf(line: &str) -> Result<Node, String> {
+    let mut cells = vec![];
+    let (mut x, mut y) = (0, 0);
+
+    for c in line.chars() {
+        match c {
+            '.' => x += 1,
+            '*' => {
+                cells.push((x, y, 1));
+                x += 1;
+            }
+            '$' => (x, y) = (0, y + 1),
+            _ => return Err(format!("Unexpected character {c:?} in leaf")),
+        }
+        if x > 8 || (y >= 8 && c != '$') || y > 8 {
+            return Err("Leaf is larger than 8x8".to_string());
+        }
+    }
+
+    Ok(Node::Leaf(cells))
+}
+
+/// Encodes a pattern, using 8x8 leaves when it only has two states
+pub fn write(pattern: &SparsePattern, rule: Option<&str>) -> String {
+    let mut text = String::from("[M2] (game-of-life)\n");
+    if let Some(rule) = rule {
+        text.push_str(&format!("#R {rule}\n"));
+    }
+
+    let Some((min_x, min_y, max_x, max_y)) = pattern.bounds() else {
+        return text;
+    };
+
+    let leaf_level = if pattern.max_state() <= 1 { 3 } else { 1 };
+    let extent = [min_x, min_y, max_x, max_y]
+        .into_iter()
+        .map(|v| if v < 0 { -v } else { v + 1 })
+        .max()
+        .unwrap_or(0);
+    let mut level = leaf_level;
+    while (1i64 << (level - 1)) < extent {
+        level += 1;
+    }
+
+    let cells: Vec<(i64, i64, usize)> = pattern
+        .cells
+        .iter()
+        .map(|(&(x, y), &state)| (x, y, state))
+        .collect();
+    let origin = -(1 << (level - 1));
+
+    let mut writer = Writer {
+        leaf_level,
+        lines: vec![],
+        indices: HashMap::new(),
+    };
+    writer.build(cells, origin, origin, level);
+
+    for line in writer.lines {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+struct Writer {
+    leaf_level: u32,
+    lines: Vec<String>,
+    /// Identical nodes are only written once
+    indices: HashMap<String, usize>,
+}
+
+impl Writer {
+    /// Writes the node covering the square at (x, y), returning its index or 0 if empty
+    fn build(&mut self, cells: Vec<(i64, i64, usize)>, x: i64, y: i64, level: u32) -> usize {
+        if cells.is_empty() {
+            return 0;
+        }
+
+        let line = if level == self.leaf_level && level == 3 {
+            let mut rows = vec![vec!['.'; 8]; 8];
+            for (cx, cy, _) in &cells {
+                rows[(cy - y) as usize][(cx - x) as usize] = '*';
+            }
+            let last_row = rows.iter().rposition(|row| row.contains(&'*')).unwrap_or(0);
+            rows[..=last_row]
+                .iter()
+                .map(|row| {
+                    let end = row.iter().rposition(|c| *c == '*').map_or(0, |i| i + 1);
+                    row[..end].iter().collect::<String>() + "$"
+                })
+                .collect()
+        } else if level == self.leaf_level {
+            let mut states = [0; 4];
+            for (cx, cy, state) in &cells {
+                states[((cy - y) * 2 + (cx - x)) as usize] = *state;
+            }
+            format!("1 {} {} {} {}", states[0], states[1], states[2], states[3])
+        } else {
+            let half = 1 << (level - 1);
+            let mut quadrants: [Vec<_>; 4] = Default::default();
+            for cell in cells {
+                let quadrant = (cell.0 >= x + half) as usize + 2 * (cell.1 >= y + half) as usize;
+                quadrants[quadrant].push(cell);
+            }
+            let [nw, ne, sw, se] = quadrants;
+            let children = [
+                self.build(nw, x, y, level - 1),
+                self.build(ne, x + half, y, level - 1),
+                self.build(sw, x, y + half, level - 1),
+                self.build(se, x + half, y + half, level - 1),
+            ];
+            format!(
+                "{level} {} {} {} {}",
+                children[0], children[1], children[2], children[3]
+            )
+        };
+
+        if let Some(index) = self.indices.get(&line) {
+            return *index;
+        }
+        self.lines.push(line.clone());
+        self.indices.insert(line, self.lines.len());
+        self.lines.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Result<Macrocell, String> {
+        Macrocell::read(text.as_bytes())
+    }
+
+    #[test]
+    fn test_read_glider() {
+        let macrocell = read("[M2] (golly 4.0)\n#R B3/S23\n.*$..*$***$\n4 1 0 0 0\n").unwrap();
+        let pattern = macrocell.crop(100, 100);
+
+        assert_eq!(pattern.rule.as_deref(), Some("B3/S23"));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, vec![0, 1, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        // Four copies of the same leaf, then four copies of that block
+        let macrocell = read("[M2]\n*$\n4 1 1 1 1\n5 2 2 2 2\n").unwrap();
+        let pattern = macrocell.crop(100, 100);
+        assert_eq!((pattern.width, pattern.height), (25, 25));
+        assert_eq!(pattern.cells.iter().filter(|&&c| c == 1).count(), 16);
+        assert_eq!(pattern.get(24, 24), 1);
+
+        let corner = macrocell.crop(9, 9);
+        assert_eq!(corner.cells.iter().filter(|&&c| c == 1).count(), 4);
+        assert_eq!(corner.get(8, 8), 1);
+    }
+
+    #[test]
+    fn test_crop_huge() {
+        // A diagonal line of leaves doubling at each level, with 2^59 live cells in all
+        let mut text = String::from("[M2]\n*$\n");
+        for level in 4..=MAX_LEVEL {
+            let child = level - 3;
+            text.push_str(&format!("{level} {child} 0 0 {child}\n"));
+        }
+        let macrocell = read(&text).unwrap();
+
+        let pattern = macrocell.crop(20, 20);
+        assert_eq!((pattern.width, pattern.height), (20, 20));
+        assert_eq!(pattern.get(0, 0), 1);
+        assert_eq!(pattern.get(16, 16), 1);
+        assert_eq!(pattern.cells.iter().filter(|&&c| c == 1).count(), 3);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(read("x = 1, y = 1\no!").is_err());
+        assert!(read("[M2]\n*$\n4 2 0 0 0\n").is_err());
+        assert!(read("[M2]\n*$\n5 1 0 0 0\n").is_err());
+        assert!(read("[M2]\n*********$\n").is_err());
+        assert!(read("[M2]\n4 1 0 0\n").is_err());
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        for states in [1, 3] {
+            let mut pattern = SparsePattern::default();
+            for (i, (x, y)) in [(0, 0), (1, 2), (-40, 7), (1000, -3000), (5, 5)]
+                .into_iter()
+                .enumerate()
+            {
+                pattern.set(x, y, i % states + 1);
+            }
+
+            let text = write(&pattern, Some("B3/S23"));
+            let read = read(&text).unwrap();
+            let cropped = read.crop(usize::MAX, usize::MAX);
+            assert_eq!(cropped.rule.as_deref(), Some("B3/S23"));
+
+            // The crop starts at the top left of the live cells
+            let (min_x, min_y, _, _) = pattern.bounds().unwrap();
+            let live: HashMap<(i64, i64), usize> = cropped
+                .cells
+                .iter()
+                .enumerate()
+                .filter(|(_, state)| **state != 0)
+                .map(|(i, state)| {
+                    let x = (i % cropped.width) as i64 + min_x;
+                    let y = (i / cropped.width) as i64 + min_y;
+                    ((x, y), *state)
+                })
+                .collect();
+            assert_eq!(live, pattern.cells, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_write_shares_nodes() {
+        let mut pattern = SparsePattern::default();
+        for i in 0..64 {
+            pattern.set(i * 8, 0, 1);
+        }
+
+        // One leaf plus one node per level, rather than one per block
+        let text = write(&pattern, None);
+        assert!(text.lines().count() < 16, "{text}");
+    }
+}
@@ -0,0 +1,118 @@
+/*
+Plaintext patterns, usually with the .cells extension
+
+!Name: NAME
+!Optional comment lines
+.O.
+..O
+OOO
+
+`.` is a dead cell and `O` (or `*`) a live one. Rows may be shorter than the pattern, and
+blank lines are empty rows.
+*/
+
+use super::{check_size, Pattern};
+
+pub fn parse(text: &str) -> Result<Pattern, String> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.starts_with('!'))
+        .collect();
+
+    // Blank lines only count as rows when followed by more cells
+    let height = rows
+        .iter()
+        .rposition(|row| !row.is_empty())
+        .map_or(0, |y| y + 1);
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0);
+    check_size(width, height)?;
+
+    let mut pattern = Pattern::new(width, height);
+    for (y, row) in rows.iter().take(height).enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            match c {
+                '.' => {}
+                'O' | '*' => pattern.set(x, y, 1),
+                _ => {
+                    return Err(format!(
+                        "Unexpected character {c:?} at line {}, column {}",
+                        y + 1,
+                        x + 1
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(pattern)
+}
+
+/// Encodes a pattern, writing every non-default cell as live since the format only has two
+/// states
+pub fn write(pattern: &Pattern, name: Option<&str>) -> String {
+    let mut text = String::new();
+    if let Some(name) = name {
+        text.push_str(&format!("!Name: {name}\n"));
+    }
+
+    for y in 0..pattern.height {
+        let end = (0..pattern.width)
+            .rposition(|x| pattern.get(x, y) != 0)
+            .map_or(0, |x| x + 1);
+        let row: String = (0..end)
+            .map(|x| if pattern.get(x, y) == 0 { '.' } else { 'O' })
+            .collect();
+        text.push_str(&row);
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_glider() {
+        let pattern = parse("!Name: Glider\n!\n.O\n..O\nOOO\n").unwrap();
+
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, vec![0, 1, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_parse_blank_rows() {
+        let pattern = parse("O\n\n*.O\n\n\n").unwrap();
+
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, vec![1, 0, 0, 0, 0, 0, 1, 0, 1]);
+        assert!(parse("O.x").is_err());
+    }
+
+    #[test]
+    fn test_parse_refuses_huge_patterns() {
+        // Small text can still describe a pattern far too large to allocate
+        let text = format!("{}\n{}O", ".".repeat(60_000), "\n".repeat(60_000));
+        assert!(parse(&text).is_err());
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let mut pattern = Pattern::new(4, 3);
+        pattern.set(0, 0, 1);
+        pattern.set(3, 2, 2);
+
+        let text = write(&pattern, Some("Test"));
+        assert_eq!(text, "!Name: Test\nO\n\n...O\n");
+
+        let mut expected = pattern.clone();
+        expected.set(3, 2, 1);
+        assert_eq!(parse(&text).unwrap(), expected);
+    }
+}
@@ -0,0 +1,403 @@
+/*
+Run Length Encoded patterns, as used by Golly and most pattern collections
+
+#C Optional comment lines
+x = WIDTH, y = HEIGHT, rule = RULE
+RUN_COUNT? TAG ... !
+
+Two state patterns use `b` for dead cells and `o` for live ones. Multi-state patterns use `.`
+for state 0, `A`..`X` for states 1..24 and `pA`..`pX`, `qA`..`qX` and so on for the states
+after that. `$` ends a row and `!` ends the pattern.
+*/
+
+use super::{check_size, Pattern, MAX_SIZE};
+
+/// Longest line written, as recommended by the format
+const LINE_LENGTH: usize = 70;
+
+pub fn parse(text: &str) -> Result<Pattern, String> {
+    let mut header: Option<(usize, usize, Option<String>)> = None;
+    let mut data = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if header.is_none() && data.is_empty() && line.starts_with('x') {
+            header = Some(parse_header(line)?);
+        } else {
+            data.push_str(line);
+        }
+    }
+
+    if let Some((width, height, _)) = &header {
+        check_size(*width, *height)?;
+    }
+    let size = header.as_ref().map(|&(width, height, _)| (width, height));
+    let rows = parse_data(&data, size)?;
+
+    let (width, height, rule) = header.unwrap_or_else(|| {
+        let width = rows
+            .iter()
+            .map(|row| row.iter().map(|(x, _)| x + 1).max().unwrap_or(0))
+            .max()
+            .unwrap_or(0);
+        (width, rows.len(), None)
+    });
+    check_size(width, height)?;
+
+    let mut pattern = Pattern::new(width, height);
+    pattern.rule = rule;
+
+    for (y, row) in rows.iter().enumerate() {
+        for &(x, state) in row {
+            pattern.set(x, y, state);
+        }
+    }
+
+    Ok(pattern)
+}
+
+fn parse_header(line: &str) -> Result<(usize, usize, Option<String>), String> {
+    let mut width = None;
+    let mut height = None;
+    let mut rule = None;
+
+    for field in line.split(',') {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid header field {field:?}"))?;
+        let value = value.trim();
+
+        match key.trim() {
+            "x" => {
+                width = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid width {value:?}"))?,
+                )
+            }
+            "y" => {
+                height = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid height {value:?}"))?,
+                )
+            }
+            "rule" => rule = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    match (width, height) {
+        (Some(width), Some(height)) => Ok((width, height, rule)),
+        _ => Err("Header must give both x and y".to_string()),
+    }
+}
+
+/// Moves along a row by a run, refusing to go past the widest pattern
+fn advance(x: usize, run: usize) -> Result<usize, String> {
+    x.checked_add(run)
+        .filter(|&x| x <= MAX_SIZE)
+        .ok_or_else(|| format!("Row is wider than {MAX_SIZE} cells"))
+}
+
+/// Decodes the cells of each row as (x, state) pairs, leaving out dead cells. Cells are
+/// checked against the header's `size` as they are decoded or, without one, the pattern
+/// they cover is kept within the largest allowed
+fn parse_data(
+    data: &str,
+    size: Option<(usize, usize)>,
+) -> Result<Vec<Vec<(usize, usize)>>, String> {
+    let mut rows: Vec<Vec<(usize, usize)>> = vec![vec![]];
+    let mut x = 0;
+    let mut width = 0;
+    let mut place = |rows: &mut Vec<Vec<(usize, usize)>>, x: usize, end: usize, state| {
+        let y = rows.len() - 1;
+        match size {
+            Some((w, h)) if end > w || y >= h => {
+                return Err(format!(
+                    "Cell at ({}, {y}) is outside the {w}x{h} pattern",
+                    end - 1
+                ));
+            }
+            Some(_) => {}
+            None => {
+                width = width.max(end);
+                check_size(width, rows.len())?;
+            }
+        }
+        rows[y].extend((x..end).map(|x| (x, state)));
+        Ok(())
+    };
+    let mut count: Option<usize> = None;
+    let mut prefix: Option<char> = None;
+
+    for c in data.chars() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if let Some(p) = prefix {
+            prefix = None;
+            let state = match c {
+                'A'..='X' => (p as usize - 'o' as usize) * 24 + (c as usize - 'A' as usize) + 1,
+                _ => return Err(format!("Invalid state {p}{c}")),
+            };
+            let end = advance(x, count.take().unwrap_or(1))?;
+            place(&mut rows, x, end, state)?;
+            x = end;
+            continue;
+        }
+
+        match c {
+            '0'..='9' => {
+                let digit = c as usize - '0' as usize;
+                count = count
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|count| count.checked_add(digit));
+                if count.is_none() {
+                    return Err("Run count is too large".to_string());
+                }
+            }
+            'b' | '.' => x = advance(x, count.take().unwrap_or(1))?,
+            'o' | 'A'..='X' => {
+                let state = if c == 'o' {
+                    1
+                } else {
+                    c as usize - 'A' as usize + 1
+                };
+                let end = advance(x, count.take().unwrap_or(1))?;
+                place(&mut rows, x, end, state)?;
+                x = end;
+            }
+            'p'..='y' => prefix = Some(c),
+            '$' => {
+                let run = count.take().unwrap_or(1);
+                if advance(rows.len(), run).is_err() {
+                    return Err(format!("Pattern is taller than {MAX_SIZE} rows"));
+                }
+                for _ in 0..run {
+                    rows.push(vec![]);
+                }
+                x = 0;
+            }
+            '!' => {
+                // Trailing empty rows carry no cells
+                while rows.len() > 1 && rows.last().is_some_and(Vec::is_empty) {
+                    rows.pop();
+                }
+                return Ok(rows);
+            }
+            _ => return Err(format!("Unexpected character {c:?} in pattern")),
+        }
+    }
+
+    Err("Pattern is missing the terminating !".to_string())
+}
+
+/// Encodes a pattern, using the two state `b`/`o` tags if `states` allows
+pub fn write(pattern: &Pattern, rule: Option<&str>, states: usize) -> String {
+    let two_state = states <= 2 && pattern.max_state() <= 1;
+    let tag = |state: usize| match (two_state, state) {
+        (true, 0) => "b".to_string(),
+        (true, _) => "o".to_string(),
+        (false, 0) => ".".to_string(),
+        (false, s) if s <= 24 => char::from(b'A' + (s - 1) as u8).to_string(),
+        (false, s) => {
+            let prefix = char::from(b'p' + ((s - 25) / 24) as u8);
+            let letter = char::from(b'A' + ((s - 25) % 24) as u8);
+            format!("{prefix}{letter}")
+        }
+    };
+
+    let mut runs: Vec<(usize, String)> = vec![];
+    let mut push = |count: usize, tag: String| {
+        if count == 0 {
+            return;
+        }
+        match runs.last_mut() {
+            Some((n, last)) if *last == tag => *n += count,
+            _ => runs.push((count, tag)),
+        }
+    };
+
+    for y in 0..pattern.height {
+        // Trailing dead cells in a row are implied by the end of row
+        let end = (0..pattern.width)
+            .rposition(|x| pattern.get(x, y) != 0)
+            .map_or(0, |x| x + 1);
+        for x in 0..end {
+            push(1, tag(pattern.get(x, y)));
+        }
+        if y + 1 < pattern.height {
+            push(1, "$".to_string());
+        }
+    }
+
+    // Rows ending the pattern need no terminator either
+    while runs.last().is_some_and(|(_, tag)| tag == "$") {
+        runs.pop();
+    }
+
+    let mut text = format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(rule) = rule {
+        text.push_str(&format!(", rule = {rule}"));
+    }
+    text.push('\n');
+
+    let mut line = String::new();
+    let items = runs
+        .into_iter()
+        .map(|(count, tag)| match count {
+            1 => tag,
+            _ => format!("{count}{tag}"),
+        })
+        .chain(std::iter::once("!".to_string()));
+
+    for item in items {
+        if line.len() + item.len() > LINE_LENGTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        line.push_str(&item);
+    }
+    text.push_str(&line);
+    text.push('\n');
+
+    text
+}
+
+/// Puts a rule into a canonical form so that, for example, "B3/S23", "b3/s23" and "23/3"
+/// compare equal
+pub fn normalise_rule(rule: &str) -> String {
+    let rule: String = rule
+        .split(':')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+
+    match rule.split_once('/') {
+        Some((b, s)) if b.starts_with('b') && s.starts_with('s') => {
+            format!("b{}/s{}", &b[1..], &s[1..])
+        }
+        Some((s, b)) if b.starts_with('b') && s.starts_with('s') => {
+            format!("b{}/s{}", &b[1..], &s[1..])
+        }
+        Some((s, b)) if is_digits(s) && is_digits(b) => format!("b{b}/s{s}"),
+        _ => rule,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static GLIDER: &str = "#N Glider
+#C A comment
+x = 3, y = 3, rule = B3/S23
+bob$2bo$3o!
+";
+
+    #[test]
+    fn test_parse_glider() {
+        let pattern = parse(GLIDER).expect("Parse failed");
+
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(pattern.cells, vec![0, 1, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_parse_multi_state() {
+        let pattern = parse("x = 5, y = 3, rule = WireWorld\n.2A$\n$C\npApByO!").unwrap();
+
+        assert_eq!((pattern.width, pattern.height), (5, 3));
+        assert_eq!(pattern.cells[..5], [0, 1, 1, 0, 0]);
+        assert_eq!(pattern.cells[5..10], [0; 5]);
+        assert_eq!(pattern.cells[10..], [3, 25, 26, 255, 0]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("x = 2, y = 1\n3o!").is_err());
+        assert!(parse("x = 3, y = 3\nbob$2bo").is_err());
+        assert!(parse("x = 3, y = 3\nb?b!").is_err());
+        assert!(parse("x = 3\nbob!").is_err());
+
+        // Huge sizes are refused rather than overflowing or exhausting memory
+        assert_eq!(
+            parse("99999999999999999999o!"),
+            Err("Run count is too large".to_string())
+        );
+        assert!(parse("x = 1, y = 1\n18446744073709551615b2o!").is_err());
+        assert!(parse("x = 1, y = 1\n4000000000$o!").is_err());
+        assert!(parse("x = 100000, y = 100000\no!").is_err());
+        assert!(parse("x = 18446744073709551615, y = 2\no!").is_err());
+    }
+
+    #[test]
+    fn test_parse_bounds_work_while_decoding() {
+        // Each run is refused as soon as it leaves the header's size
+        let hostile = format!("x = 1, y = 1\n{}!", "65536o$".repeat(100_000));
+        assert_eq!(
+            parse(&hostile),
+            Err("Cell at (65535, 0) is outside the 1x1 pattern".to_string())
+        );
+
+        // Without a header, the cells decoded so far must fit the largest pattern
+        assert_eq!(
+            parse("65536o1000$o!"),
+            Err(format!(
+                "Pattern is too large at 65536x1001, the most is {} cells",
+                crate::pattern::MAX_CELLS
+            ))
+        );
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let glider = parse(GLIDER).unwrap();
+        let text = write(&glider, Some("B3/S23"), 2);
+        assert_eq!(text, "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n");
+        assert_eq!(parse(&text).unwrap(), glider);
+
+        let mut multi = Pattern::new(30, 4);
+        multi.set(0, 0, 3);
+        multi.set(29, 0, 3);
+        multi.set(5, 3, 40);
+        let text = write(&multi, None, 41);
+        assert_eq!(text, "x = 30, y = 4\nC28.C3$5.pP!\n");
+        assert_eq!(parse(&text).unwrap(), multi);
+    }
+
+    #[test]
+    fn test_write_wraps_lines() {
+        let mut pattern = Pattern::new(200, 1);
+        for x in (0..200).step_by(2) {
+            pattern.set(x, 0, 1);
+        }
+        let text = write(&pattern, None, 2);
+
+        assert!(text.lines().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(parse(&text).unwrap(), pattern);
+    }
+
+    #[test]
+    fn test_normalise_rule() {
+        assert_eq!(normalise_rule("B3/S23"), "b3/s23");
+        assert_eq!(normalise_rule("b3/s23:T100,100"), "b3/s23");
+        assert_eq!(normalise_rule("23/3"), "b3/s23");
+        assert_eq!(normalise_rule("S23/B3"), "b3/s23");
+        assert_eq!(normalise_rule("WireWorld"), "wireworld");
+    }
+}
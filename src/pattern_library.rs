@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    pattern::{self, rle, Pattern},
+    ruleset::Ruleset,
+    ruleset_library::{self, display_name, LibraryEntry},
+    world_view,
+};
+
+/// Largest Macrocell pattern placed in the library, since they can be any size
+const MAX_PATTERN_SIZE: usize = 1024;
+/// Largest side of a thumbnail in pixels, bigger patterns are sampled down to fit
+const MAX_THUMBNAIL_SIZE: usize = 128;
+/// What thumbnails show where there are no cells
+const THUMBNAIL_BACKGROUND: [u8; 4] = [20, 20, 20, 255];
+
+/// Patterns always in the library, as (name, RLE)
+static BUNDLED_PATTERNS: [(&str, &str); 7] = [
+    ("Glider", include_str!("../patterns/glider.rle")),
+    (
+        "Lightweight Spaceship",
+        include_str!("../patterns/lightweight_spaceship.rle"),
+    ),
+    ("R Pentomino", include_str!("../patterns/r_pentomino.rle")),
+    ("Pulsar", include_str!("../patterns/pulsar.rle")),
+    (
+        "Gosper Glider Gun",
+        include_str!("../patterns/gosper_glider_gun.rle"),
+    ),
+    ("Replicator", include_str!("../patterns/replicator.rle")),
+    ("Clock", include_str!("../patterns/clock.rle")),
+];
+
+/// A pattern that can be picked from the pattern library
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct PatternEntry {
+    pub name: String,
+    pub pattern: Pattern,
+    /// The file the pattern was read from, or `None` if it is built in
+    pub path: Option<PathBuf>,
+}
+
+impl LibraryEntry for PatternEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads a pattern file in any supported format, naming it after the file
+    fn load(path: &Path) -> Result<PatternEntry, String> {
+        let pattern =
+            pattern::read_file(&path.to_string_lossy(), MAX_PATTERN_SIZE, MAX_PATTERN_SIZE)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        Ok(PatternEntry {
+            name: display_name(&stem),
+            pattern,
+            path: Some(path.to_path_buf()),
+        })
+    }
+}
+
+impl PatternEntry {
+    /// Whether the pattern was made for a ruleset. Patterns which don't name a rule are
+    /// offered for every ruleset with enough states.
+    pub fn suits(&self, ruleset: &Ruleset) -> bool {
+        let rule_matches = match (&self.pattern.rule, &ruleset.rle_rule) {
+            (None, _) => true,
+            (Some(rule), Some(rle_rule)) => {
+                rle::normalise_rule(rule) == rle::normalise_rule(rle_rule)
+            }
+            (Some(_), None) => false,
+        };
+        rule_matches && self.pattern.max_state() < ruleset.states.len()
+    }
+}
+
+/// The built-in patterns followed by every pattern file in `dirs`, along with why any other
+/// pattern files were skipped
+pub fn discover(dirs: &[PathBuf]) -> (Vec<PatternEntry>, Vec<String>) {
+    let mut entries: Vec<PatternEntry> = BUNDLED_PATTERNS
+        .iter()
+        .map(|(name, text)| PatternEntry {
+            name: name.to_string(),
+            pattern: pattern::parse(text, MAX_PATTERN_SIZE, MAX_PATTERN_SIZE)
+                .expect("Bundled patterns are valid"),
+            path: None,
+        })
+        .collect();
+    let extensions = ["rle", "cells", "lif", "life", "mc"];
+    let problems = ruleset_library::load_files(&mut entries, dirs, &extensions);
+    (entries, problems)
+}
+
+/// A square RGBA picture of a pattern, centred, with state `i` drawn in `colours[i]`.
+/// Big patterns are reduced a block of cells to a pixel, as the world is when zoomed out.
+/// Returns the side length in pixels along with the bytes.
+pub fn thumbnail(pattern: &Pattern, colours: &[[u8; 4]]) -> (usize, Vec<u8>) {
+    let side = pattern.width.max(pattern.height).max(1);
+    let offset = ((side - pattern.width) / 2, (side - pattern.height) / 2);
+    let scale = side.div_ceil(MAX_THUMBNAIL_SIZE);
+
+    let (pixels, _, bytes) = world_view::block_pixels(side, side, scale, |x, y| {
+        let state = x
+            .checked_sub(offset.0)
+            .zip(y.checked_sub(offset.1))
+            .filter(|&(x, y)| x < pattern.width && y < pattern.height)
+            .map_or(0, |(x, y)| pattern.get(x, y));
+        match state {
+            0 => (true, THUMBNAIL_BACKGROUND),
+            state => (false, colours.get(state).copied().unwrap_or([255; 4])),
+        }
+    });
+
+    (pixels, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use std::fs;
+
+    fn ruleset(json: &str) -> Ruleset {
+        Ruleset::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_suits() {
+        let life = ruleset(include_str!("../rulesets/game_of_life.json"));
+        let wireworld = ruleset(include_str!("../rulesets/wireworld.json"));
+        let (entries, problems) = discover(&[]);
+        assert!(problems.is_empty());
+
+        let suited = |ruleset: &Ruleset| -> Vec<&str> {
+            entries
+                .iter()
+                .filter(|e| e.suits(ruleset))
+                .map(|e| e.name.as_str())
+                .collect()
+        };
+        assert!(suited(&life).contains(&"Glider"));
+        assert!(!suited(&life).contains(&"Replicator"));
+        assert_eq!(suited(&wireworld), vec!["Clock"]);
+
+        let untitled = PatternEntry {
+            name: "Untitled".to_string(),
+            pattern: pattern::parse("!Name: Blinker\nOOO", 3, 1).unwrap(),
+            path: None,
+        };
+        assert!(untitled.suits(&life) && untitled.suits(&wireworld));
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = TempDir::new("pattern-library");
+        fs::write(dir.join("beehive.cells"), ".OO.\nO..O\n.OO.").unwrap();
+        fs::write(dir.join("glider.rle"), "x = 1, y = 1, rule = B3/S23\no!").unwrap();
+        fs::write(dir.join("broken.rle"), "x = 2, y = 2\n2o$2q!").unwrap();
+        fs::write(dir.join("notes.txt"), "not a pattern").unwrap();
+
+        let (entries, problems) = discover(&[dir.path().to_path_buf()]);
+
+        let glider = entries.iter().find(|e| e.name == "Glider").unwrap();
+        assert_eq!(glider.path, Some(dir.join("glider.rle")));
+        assert_eq!(glider.pattern.cells, vec![1]);
+        let beehive = entries.last().unwrap();
+        assert_eq!(beehive.name, "Beehive");
+        assert_eq!((beehive.pattern.width, beehive.pattern.height), (4, 3));
+        assert_eq!(entries.len(), BUNDLED_PATTERNS.len() + 1);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("broken.rle"), "{problems:?}");
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        // .O.
+        // ..O
+        let mut pattern = Pattern::new(3, 2);
+        pattern.set(1, 0, 1);
+        pattern.set(2, 1, 2);
+        let colours = [[0, 0, 0, 0], [1, 1, 1, 255], [2, 2, 2, 255]];
+
+        let (side, bytes) = thumbnail(&pattern, &colours);
+        assert_eq!(side, 3);
+        let pixels: Vec<[u8; 4]> = bytes.chunks(4).map(|p| p.try_into().unwrap()).collect();
+        let b = THUMBNAIL_BACKGROUND;
+        assert_eq!(
+            pixels,
+            vec![b, colours[1], b, b, b, colours[2], b, b, b],
+            "the pattern is centred vertically, with the spare row at the bottom"
+        );
+
+        // Big patterns are reduced 4x4 cells to a pixel, with any live cell showing
+        let mut wide = Pattern::new(500, 20);
+        wide.set(499, 19, 2);
+        let (side, bytes) = thumbnail(&wide, &colours);
+        assert_eq!(side, 125);
+        assert!(side <= MAX_THUMBNAIL_SIZE);
+        assert_eq!(bytes.len(), side * side * 4);
+        // Row 259 of the square, which is the pattern's last
+        let pixel = (259 / 4 * side + 499 / 4) * 4;
+        assert_eq!(bytes[pixel..pixel + 4], colours[2]);
+    }
+}
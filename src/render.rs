@@ -0,0 +1,175 @@
+use std::fs::File;
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba, RgbaImage,
+};
+
+use crate::world::World;
+
+static GRID_COLOUR: Rgba<u8> = Rgba([40, 40, 40, 255]);
+
+/// Most pixels in a rendered image, so a large world and cell size can't exhaust memory
+pub const MAX_PIXELS: u64 = 1 << 26;
+
+pub(crate) struct RenderOptions {
+    /// Width and height of each cell in pixels
+    pub cell_size: u32,
+    /// Draws a line along the bottom and right of every cell
+    pub grid_lines: bool,
+}
+
+/// Draws every cell in its current colour, including any `paint` override, onto black
+pub fn render(world: &World, options: &RenderOptions) -> Result<RgbaImage, String> {
+    let size = options.cell_size.max(1);
+    let scale = |cells: usize| u32::try_from(cells).ok()?.checked_mul(size);
+    let (width, height) = scale(world.width)
+        .zip(scale(world.height))
+        .filter(|&(width, height)| width as u64 * height as u64 <= MAX_PIXELS)
+        .ok_or_else(|| {
+            format!(
+                "A {}x{} world at {size} pixels per cell is larger than {MAX_PIXELS} pixels",
+                world.width, world.height
+            )
+        })?;
+    let mut image = RgbaImage::new(width, height);
+
+    for (i, cell) in world.cells.iter().enumerate() {
+        let (x, y) = ((i % world.width) as u32, (i / world.width) as u32);
+        let [r, g, b, a]: [u8; 4] = cell.colour.into();
+        let blend = |channel: u8| (channel as u32 * a as u32 / 255) as u8;
+        let colour = Rgba([blend(r), blend(g), blend(b), 255]);
+
+        for dy in 0..size {
+            for dx in 0..size {
+                let on_grid = options.grid_lines && size > 1 && (dx == size - 1 || dy == size - 1);
+                let pixel = if on_grid { GRID_COLOUR } else { colour };
+                image.put_pixel(x * size + dx, y * size + dy, pixel);
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+pub fn save_png(image: &RgbaImage, path: &str) -> Result<(), String> {
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("Could not write {path}: {e}"))
+}
+
+/// Writes frames to an animated GIF as they are rendered, looping forever
+pub(crate) struct GifWriter {
+    encoder: GifEncoder<File>,
+    delay: Delay,
+    path: String,
+}
+
+impl GifWriter {
+    pub fn create(path: &str, delay_ms: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Could not write {path}: {e}"))?;
+        let mut encoder = GifEncoder::new(file);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| format!("Could not write {path}: {e}"))?;
+
+        Ok(GifWriter {
+            encoder,
+            delay: Delay::from_numer_denom_ms(delay_ms, 1),
+            path: path.to_string(),
+        })
+    }
+
+    pub fn add_frame(&mut self, image: RgbaImage) -> Result<(), String> {
+        self.encoder
+            .encode_frame(Frame::from_parts(image, 0, 0, self.delay))
+            .map_err(|e| format!("Could not write {}: {e}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{world, TempDir, LIFE, WIREWORLD};
+    use image::AnimationDecoder;
+    use macroquad::prelude::Color;
+
+    #[test]
+    fn test_render_cells() {
+        let mut world = world(WIREWORLD, (4, 3), Some("x = 3, y = 1\nABC!"));
+        world.cells[4].colour = Color::from_rgba(10, 20, 30, 255);
+
+        let image = render(
+            &world,
+            &RenderOptions {
+                cell_size: 2,
+                grid_lines: false,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(image.dimensions(), (8, 6));
+        assert_eq!(*image.get_pixel(1, 1), Rgba([0, 0, 255, 255]));
+        assert_eq!(*image.get_pixel(2, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(5, 1), Rgba([255, 255, 0, 255]));
+        // Transparent cells are drawn on black
+        assert_eq!(*image.get_pixel(7, 5), Rgba([0, 0, 0, 255]));
+        // Painted cells keep their paint
+        assert_eq!(*image.get_pixel(0, 2), Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn test_render_grid_lines() {
+        let world = world(LIFE, (4, 3), Some("x = 1, y = 1\no!"));
+        let image = render(
+            &world,
+            &RenderOptions {
+                cell_size: 3,
+                grid_lines: true,
+            },
+        )
+        .unwrap();
+
+        let alive = *image.get_pixel(0, 0);
+        assert_eq!(*image.get_pixel(1, 1), alive);
+        assert_ne!(alive, GRID_COLOUR);
+        assert_eq!(*image.get_pixel(2, 0), GRID_COLOUR);
+        assert_eq!(*image.get_pixel(0, 2), GRID_COLOUR);
+    }
+
+    #[test]
+    fn test_render_refuses_huge_images() {
+        let world = world(LIFE, (4, 3), Some("o!"));
+        let options = RenderOptions {
+            cell_size: u32::MAX,
+            grid_lines: false,
+        };
+        assert!(render(&world, &options).is_err());
+    }
+
+    #[test]
+    fn test_gif_frames() {
+        let mut world = world(LIFE, (4, 3), Some("x = 3, y = 1\n3o!"));
+        let options = RenderOptions {
+            cell_size: 1,
+            grid_lines: false,
+        };
+        let dir = TempDir::new("render");
+        let path = dir.join("frames.gif");
+        let path = path.to_str().unwrap();
+
+        let mut gif = GifWriter::create(path, 50).unwrap();
+        for _ in 0..3 {
+            gif.add_frame(render(&world, &options).unwrap()).unwrap();
+            world.next_generation();
+        }
+        drop(gif);
+
+        let decoder = image::codecs::gif::GifDecoder::new(File::open(path).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].buffer().dimensions(), (4, 3));
+        assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(50, 1));
+    }
+}
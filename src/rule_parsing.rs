@@ -2,30 +2,37 @@
 Rules are define as boolean logic
 
 CONDITIONS := CONDITIONS ; CONDITION || CONDITION
-CONDITION := STATE_NAME OP COMPARE_TO
+CONDITION := STATE_REF OP COMPARE_TO
 OP := '==' || '>' || '<' || '>=' || '<=' || '!='
-COMPARE_TO := STATE_NAME || numeric
-STATE_NAME := $ alpha_numeric+
+COMPARE_TO := STATE_REF || numeric
+STATE_REF := '$self' || '$*' || '$any_except(' NAME ')' || STATE_NAME
+STATE_NAME := $ NAME
+NAME := alpha_numeric+
+
+`$self` counts neighbours in the same state as the current cell, `$*` counts every
+neighbour not in the default state and `$any_except(x)` counts every neighbour not in x.
+A STATE_NAME may name either a state or a group of states.
+
+The whole rule must match CONDITIONS, surrounding whitespace aside.
 */
 
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while1},
     character::complete::{char, digit1, multispace0},
-    combinator::{map, map_res},
-    multi::separated_list1,
-    sequence::{delimited, preceded},
+    combinator::{cut, map, map_res, opt},
+    sequence::{delimited, preceded, terminated},
     IResult, Parser,
 };
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) struct Condition {
-    pub state: String,
+    pub state: StateRef,
     pub op: Op,
     pub compare_to: CompareTo,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) enum Op {
     Eq,
     Gt,
@@ -35,42 +42,113 @@ pub(crate) enum Op {
     Ne,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) enum CompareTo {
-    State(String),
+    State(StateRef),
     Value(usize),
 }
 
-pub fn parse_conditions(input: &str) -> IResult<&str, Vec<Condition>> {
-    separated_list1(
-        delimited(multispace0, char(';'), multispace0),
-        parse_condition,
-    )
-    .parse(input)
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum StateRef {
+    /// A state or group of states
+    Named(String),
+    /// `$self`
+    Current,
+    /// `$*`
+    NonDefault,
+    /// `$any_except(x)`
+    AnyExcept(String),
 }
 
-fn parse_condition(input: &str) -> IResult<&str, Condition> {
-    map(
-        (
-            parse_state_name,
-            delimited(multispace0, parse_operator, multispace0),
-            parse_compare_to,
-        ),
-        |(state, op, compare_to)| Condition {
+/// Why a rule could not be parsed, with the 1-based column of the offending text in characters
+#[derive(PartialEq, Debug)]
+pub(crate) struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+/// Parses an entire rule, rejecting anything that does not form part of a condition
+pub fn parse_conditions(input: &str) -> Result<Vec<Condition>, ParseError> {
+    let error = |rest: &str, expected: &str| {
+        let found = match rest.split_whitespace().next() {
+            Some(token) => format!("{token:?}"),
+            None => "end of rule".to_string(),
+        };
+        ParseError {
+            column: input[..input.len() - rest.len()].chars().count() + 1,
+            message: format!("expected {expected}, found {found}"),
+        }
+    };
+
+    let reference_error = |rest: &str, e: nom::Err<nom::error::Error<&str>>, expected: &str| match e
+    {
+        nom::Err::Failure(e) => error(e.input, "a state name followed by `)`"),
+        _ => error(rest, expected),
+    };
+
+    let mut conditions = vec![];
+    let mut rest = input.trim_start();
+
+    loop {
+        let (r, state) =
+            parse_state_ref(rest).map_err(|e| reference_error(rest, e, "a state reference"))?;
+        let r = r.trim_start();
+        let (r, op) = parse_operator(r).map_err(|_| error(r, "a comparison operator"))?;
+        let r = r.trim_start();
+        let (r, compare_to) = parse_compare_to(r)
+            .map_err(|e| reference_error(r, e, "a state reference or number"))?;
+
+        conditions.push(Condition {
             state,
             op,
             compare_to,
-        },
-    )
+        });
+
+        let r = r.trim_start();
+        if r.is_empty() {
+            return Ok(conditions);
+        }
+
+        rest = match char::<&str, nom::error::Error<&str>>(';').parse(r) {
+            Ok((r, _)) => r.trim_start(),
+            Err(_) => return Err(error(r, "`;` or the end of the rule")),
+        };
+    }
+}
+
+fn parse_state_ref(input: &str) -> IResult<&str, StateRef> {
+    alt((
+        map(tag("$*"), |_| StateRef::NonDefault),
+        map(
+            preceded(
+                tag("$any_except("),
+                // Once `$any_except(` is seen it cannot be anything else
+                cut(terminated(
+                    delimited(
+                        multispace0,
+                        preceded(opt(char('$')), parse_name),
+                        multispace0,
+                    ),
+                    char(')'),
+                )),
+            ),
+            StateRef::AnyExcept,
+        ),
+        map(parse_state_name, |name| match name.as_str() {
+            "self" => StateRef::Current,
+            _ => StateRef::Named(name),
+        }),
+    ))
     .parse(input)
 }
 
 fn parse_state_name(input: &str) -> IResult<&str, String> {
+    preceded(char('$'), parse_name).parse(input)
+}
+
+fn parse_name(input: &str) -> IResult<&str, String> {
     map(
-        preceded(
-            char('$'),
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        ),
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
         String::from,
     )
     .parse(input)
@@ -90,7 +168,7 @@ fn parse_operator(input: &str) -> IResult<&str, Op> {
 
 fn parse_compare_to(input: &str) -> IResult<&str, CompareTo> {
     alt((
-        map(parse_state_name, CompareTo::State),
+        map(parse_state_ref, CompareTo::State),
         map_res(digit1, |s: &str| s.parse::<usize>().map(CompareTo::Value)),
     ))
     .parse(input)
@@ -114,7 +192,10 @@ mod tests {
     fn test_parse_compare_to_state() {
         let input = "$state_name";
         let result = parse_compare_to(input).unwrap();
-        assert_eq!(result.1, CompareTo::State("state_name".to_string()));
+        assert_eq!(
+            result.1,
+            CompareTo::State(StateRef::Named("state_name".to_string()))
+        );
     }
 
     #[test]
@@ -134,29 +215,166 @@ mod tests {
     #[test]
     fn test_parse_condition() {
         let input = "$state1 == 123";
-        let result = parse_condition(input).unwrap();
-        assert_eq!(result.1.state, "state1".to_string());
-        assert_eq!(result.1.op, Op::Eq);
-        assert_eq!(result.1.compare_to, CompareTo::Value(123));
+        let result = parse_conditions(input).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].state, StateRef::Named("state1".to_string()));
+        assert_eq!(result[0].op, Op::Eq);
+        assert_eq!(result[0].compare_to, CompareTo::Value(123));
     }
 
     #[test]
     fn test_parse_conditions() {
         let input = "$state1 == 123; $state2 != $state3";
-        let result = parse_conditions(input).unwrap();
-        let conditions = result.1;
+        let conditions = parse_conditions(input).unwrap();
 
         assert_eq!(conditions.len(), 2);
 
-        assert_eq!(conditions[0].state, "state1".to_string());
+        assert_eq!(conditions[0].state, StateRef::Named("state1".to_string()));
         assert_eq!(conditions[0].op, Op::Eq);
         assert_eq!(conditions[0].compare_to, CompareTo::Value(123));
 
-        assert_eq!(conditions[1].state, "state2".to_string());
+        assert_eq!(conditions[1].state, StateRef::Named("state2".to_string()));
         assert_eq!(conditions[1].op, Op::Ne);
         assert_eq!(
             conditions[1].compare_to,
-            CompareTo::State("state3".to_string())
+            CompareTo::State(StateRef::Named("state3".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_state_ref() {
+        assert_eq!(parse_state_ref("$self").unwrap().1, StateRef::Current);
+        assert_eq!(parse_state_ref("$*").unwrap().1, StateRef::NonDefault);
+        assert_eq!(
+            parse_state_ref("$any_except(dead)").unwrap().1,
+            StateRef::AnyExcept("dead".to_string())
+        );
+        assert_eq!(
+            parse_state_ref("$any_except( $dead )").unwrap().1,
+            StateRef::AnyExcept("dead".to_string())
+        );
+        assert_eq!(
+            parse_state_ref("$self_2").unwrap().1,
+            StateRef::Named("self_2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_conditions_wildcards() {
+        let input = "$* >= 2; $self < $any_except(empty)";
+        let conditions = parse_conditions(input).unwrap();
+
+        assert_eq!(conditions.len(), 2);
+        assert_eq!(conditions[0].state, StateRef::NonDefault);
+        assert_eq!(conditions[1].state, StateRef::Current);
+        assert_eq!(
+            conditions[1].compare_to,
+            CompareTo::State(StateRef::AnyExcept("empty".to_string()))
         );
     }
+
+    #[test]
+    fn test_parse_conditions_surrounding_whitespace() {
+        let conditions = parse_conditions("  $a == 1 ;$b<2  ").unwrap();
+        assert_eq!(conditions.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_conditions_rejects_malformed_rules() {
+        let cases = [
+            (
+                "$a == 3 junk",
+                9,
+                r#"expected `;` or the end of the rule, found "junk""#,
+            ),
+            (
+                "$a == 3 , $b == 1",
+                9,
+                r#"expected `;` or the end of the rule, found ",""#,
+            ),
+            (
+                "$a == 3;",
+                9,
+                "expected a state reference, found end of rule",
+            ),
+            (
+                "$a == 3; junk",
+                10,
+                r#"expected a state reference, found "junk""#,
+            ),
+            (
+                "$a == 3 ;; $b == 1",
+                10,
+                r#"expected a state reference, found ";""#,
+            ),
+            (
+                "$a == 3x",
+                8,
+                r#"expected `;` or the end of the rule, found "x""#,
+            ),
+            (
+                "$a === 3",
+                6,
+                r#"expected a state reference or number, found "=""#,
+            ),
+            (
+                "$a => 3",
+                4,
+                r#"expected a comparison operator, found "=>""#,
+            ),
+            ("$a 3", 4, r#"expected a comparison operator, found "3""#),
+            (
+                "$a ==",
+                6,
+                "expected a state reference or number, found end of rule",
+            ),
+            ("a == 3", 1, r#"expected a state reference, found "a""#),
+            ("$ == 3", 1, r#"expected a state reference, found "$""#),
+            ("", 1, "expected a state reference, found end of rule"),
+            ("   ", 4, "expected a state reference, found end of rule"),
+            (
+                "$any_except(a == 3",
+                15,
+                r#"expected a state name followed by `)`, found "==""#,
+            ),
+            (
+                "$any_except() > 1",
+                13,
+                r#"expected a state name followed by `)`, found ")""#,
+            ),
+            (
+                "$a > $any_except(",
+                18,
+                "expected a state name followed by `)`, found end of rule",
+            ),
+            (
+                "$a == -1",
+                7,
+                r#"expected a state reference or number, found "-1""#,
+            ),
+            // Columns count characters, not bytes
+            (
+                "$état > 1 x",
+                11,
+                r#"expected `;` or the end of the rule, found "x""#,
+            ),
+            (
+                "$a == 99999999999999999999999",
+                7,
+                r#"expected a state reference or number, found "99999999999999999999999""#,
+            ),
+        ];
+
+        for (input, column, message) in cases {
+            let error = parse_conditions(input).expect_err(input);
+            assert_eq!(
+                error,
+                ParseError {
+                    column,
+                    message: message.to_string()
+                },
+                "{input}"
+            );
+        }
+    }
 }
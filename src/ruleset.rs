@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 
+use indexmap::IndexMap;
+
 use serde::de::{self, Deserializer};
 use serde::Deserialize;
 
-use crate::rule_parsing::{parse_conditions, CompareTo, Condition, Op};
+use crate::rule_parsing::{parse_conditions, CompareTo, Condition, Op, StateRef};
+use crate::validation::{validate, RulesetError};
 /*
 {
-    "neighbourhood": String
+    "default_state": String,
+    "rle_rule": String,
+    "neighbourhood": String,
 
     "states": {
         RULE_NAME: {
@@ -18,18 +23,223 @@ use crate::rule_parsing::{parse_conditions, CompareTo, Condition, Op};
                 }
             }
         }
+    },
+
+    "groups": {
+        GROUP_NAME: [STATE_NAME, ...]
+    },
+
+    "families": {
+        FAMILY_NAME: {
+            "count": usize,
+            "colours": [COLOUR, ...],
+            "rules": {
+                "RULE_ENCODING": {
+                    "next": String,
+                    "paint": COLOUR,
+                }
+            }
+        }
     }
 }
+
+A family expands into the states FAMILY_NAME_1 ..= FAMILY_NAME_N, coloured along the
+gradient given by "colours". Within a family's rules `$next` and `$prev` refer to the
+neighbouring members of the family, wrapping around at either end.
+
+"rle_rule" optionally names the rule used for the ruleset in RLE pattern files, such as
+"B3/S23", with states numbered from the default state followed by the others in order.
+
+Rules are tried in the order they are written, the first to match deciding the next
+state, with "otherwise" used if none match.
+
+A group can be referenced in rules like a state, `$GROUP_NAME` counting neighbours in any
+of its member states.
 */
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct Ruleset {
     pub default_state: String,
     pub neighbourhood: Neighbourhood,
-    pub states: HashMap<String, RulesetState>,
+    pub rle_rule: Option<String>,
+    pub states: IndexMap<String, RulesetState>,
+    pub groups: IndexMap<String, Vec<String>>,
+    pub families: IndexMap<String, StateFamily>,
+}
+
+#[derive(Deserialize)]
+struct RulesetDefinition {
+    default_state: String,
+    neighbourhood: Neighbourhood,
+    rle_rule: Option<String>,
+    #[serde(default)]
+    states: IndexMap<String, RulesetStateDefinition>,
+    #[serde(default)]
+    groups: IndexMap<String, Vec<String>>,
+    #[serde(default)]
+    families: IndexMap<String, StateFamily>,
 }
 
-#[derive(Deserialize, Debug)]
+impl RulesetDefinition {
+    fn build(self) -> Result<Ruleset, Vec<RulesetError>> {
+        let mut errors = vec![];
+        let mut states = IndexMap::new();
+
+        for (name, definition) in self.states {
+            let (state, state_errors) = RulesetState::parse(definition);
+            errors.extend(
+                state_errors
+                    .into_iter()
+                    .map(|e| e.within(&format!("states.{name}"))),
+            );
+            states.insert(name, state);
+        }
+
+        for (name, family) in &self.families {
+            let (expanded, family_errors) = family.expand(name);
+            errors.extend(
+                family_errors
+                    .into_iter()
+                    .map(|e| e.within(&format!("families.{name}"))),
+            );
+
+            for (state_name, state) in expanded {
+                if states.contains_key(&state_name) {
+                    errors.push(RulesetError::new(
+                        format!("families.{name}"),
+                        format!("family redefines state `{state_name}`"),
+                    ));
+                }
+                states.insert(state_name, state);
+            }
+        }
+
+        for group in self.groups.keys().filter(|g| states.contains_key(*g)) {
+            errors.push(RulesetError::new(
+                format!("groups.{group}"),
+                "group has the same name as a state",
+            ));
+        }
+
+        let ruleset = Ruleset {
+            default_state: self.default_state,
+            neighbourhood: self.neighbourhood,
+            rle_rule: self.rle_rule,
+            states,
+            groups: self.groups,
+            families: self.families,
+        };
+
+        errors.extend(validate(&ruleset));
+        if errors.is_empty() {
+            Ok(ruleset)
+        } else {
+            errors.sort_by(|a, b| a.path.cmp(&b.path).then(a.column.cmp(&b.column)));
+            Err(errors)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Ruleset {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RulesetDefinition::deserialize(deserializer)?
+            .build()
+            .map_err(|errors| {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                de::Error::custom(messages.join("\n"))
+            })
+    }
+}
+
+impl Ruleset {
+    /// Parses and validates a ruleset, reporting every problem found rather than just the first
+    pub fn from_json(json: &str) -> Result<Ruleset, Vec<RulesetError>> {
+        let definition: RulesetDefinition =
+            serde_json::from_str(json).map_err(|e| vec![RulesetError::new("", e.to_string())])?;
+
+        definition.build()
+    }
+
+    /// Every state name, the default state first and the rest in the order they were defined
+    pub fn state_names(&self) -> Vec<&str> {
+        std::iter::once(self.default_state.as_str())
+            .chain(
+                self.states
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|name| *name != self.default_state),
+            )
+            .collect()
+    }
+
+    /// Finds the outcome for a cell in state `current` given the states of its neighbours
+    pub fn transition(
+        &self,
+        current: &str,
+        neighbour_counts: &HashMap<String, usize>,
+    ) -> Option<&RulesetOutcome> {
+        let neighbours = Neighbours {
+            counts: neighbour_counts,
+            current,
+            default_state: &self.default_state,
+            groups: &self.groups,
+        };
+
+        self.states.get(current)?.transition(&neighbours)
+    }
+}
+
+/// The context a rule is evaluated in
+pub struct Neighbours<'a> {
+    pub counts: &'a HashMap<String, usize>,
+    pub current: &'a str,
+    pub default_state: &'a str,
+    pub groups: &'a IndexMap<String, Vec<String>>,
+}
+
+impl Neighbours<'_> {
+    fn count(&self, state: &StateRef) -> usize {
+        match state {
+            StateRef::Named(name) => self.count_named(name),
+            StateRef::Current => self.count_named(self.current),
+            StateRef::NonDefault => self.total() - self.count_named(self.default_state),
+            StateRef::AnyExcept(name) => self.total() - self.count_named(name),
+        }
+    }
+
+    fn count_named(&self, name: &str) -> usize {
+        match self.groups.get(name) {
+            Some(members) => members
+                .iter()
+                .map(|member| self.counts.get(member).unwrap_or(&0))
+                .sum(),
+            None => *self.counts.get(name).unwrap_or(&0),
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+}
+
+/// Rewrites the `count` of a family in a ruleset's JSON source, leaving everything else intact
+pub fn set_family_count(json: &str, family: &str, count: usize) -> Result<String, String> {
+    let mut value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+    let family_value = value
+        .get_mut("families")
+        .and_then(|families| families.get_mut(family))
+        .and_then(|family| family.as_object_mut())
+        .ok_or_else(|| format!("No family {family} defined"))?;
+    family_value.insert("count".to_string(), count.into());
+
+    serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize, Clone, Debug)]
 pub enum Neighbourhood {
     Individual,
     Moore { range: usize, include_self: bool },
@@ -75,12 +285,140 @@ impl Neighbourhood {
     }
 }
 
-#[derive(Debug)]
+#[derive(Deserialize, Clone, Debug)]
+pub struct StateFamily {
+    pub count: usize,
+    pub colours: Vec<RulesetColour>,
+    #[serde(default)]
+    pub rules: IndexMap<String, RulesetOutcome>,
+    pub otherwise: Option<RulesetOutcome>,
+}
+
+impl StateFamily {
+    /// Generates the concrete states `{name}_1 ..= {name}_{count}`, alongside any problems
+    /// with the family's definition
+    pub fn expand(&self, name: &str) -> (Vec<(String, RulesetState)>, Vec<RulesetError>) {
+        let mut errors = vec![];
+        if self.count == 0 {
+            errors.push(RulesetError::new("count", "must be at least 1"));
+        }
+        if self.colours.is_empty() {
+            errors.push(RulesetError::new(
+                "colours",
+                "must define at least one colour",
+            ));
+        }
+
+        // Report errors against the templates so they point at what was actually written
+        for rule in self.rules.keys() {
+            if let Err(e) = parse_rule(rule) {
+                errors.push(e.within(&format!("rules[{rule:?}]")));
+            }
+        }
+
+        let member = |i: usize| self.member(name, i);
+
+        let states = (0..self.count)
+            .map(|i| {
+                let next = member((i + 1) % self.count);
+                let prev = member((i + self.count - 1) % self.count);
+
+                let substitute = |outcome: &RulesetOutcome| RulesetOutcome {
+                    next: match outcome.next.as_str() {
+                        "$next" => next.clone(),
+                        "$prev" => prev.clone(),
+                        other => other.to_string(),
+                    },
+                    paint: outcome.paint.clone(),
+                };
+
+                let rules = self
+                    .rules
+                    .iter()
+                    .map(|(rule, outcome)| {
+                        let rule = substitute_reference(rule, "next", &next);
+                        let rule = substitute_reference(&rule, "prev", &prev);
+                        (rule, substitute(outcome))
+                    })
+                    .collect();
+
+                let (state, _) = RulesetState::parse(RulesetStateDefinition {
+                    colour: self.gradient(i),
+                    rules,
+                    otherwise: self.otherwise.as_ref().map(substitute),
+                });
+
+                (member(i), state)
+            })
+            .collect();
+
+        (states, errors)
+    }
+
+    /// The name of the `i`th (zero based) state generated by the family
+    pub fn member(&self, name: &str, i: usize) -> String {
+        format!("{name}_{}", i + 1)
+    }
+
+    /// Linearly interpolates the colour stops for the `i`th member of the family
+    fn gradient(&self, i: usize) -> RulesetColour {
+        let stops: Vec<[u8; 4]> = self.colours.iter().map(RulesetColour::rgba).collect();
+        if stops.is_empty() {
+            return RulesetColour::Rgba(0, 0, 0, 0);
+        } else if self.count == 1 || stops.len() == 1 {
+            let [r, g, b, a] = stops[0];
+            return RulesetColour::Rgba(r, g, b, a);
+        }
+
+        let position = i as f32 / (self.count - 1) as f32 * (stops.len() - 1) as f32;
+        let from = (position.floor() as usize).min(stops.len() - 2);
+        let t = position - from as f32;
+
+        let lerp = |c: usize| {
+            let (a, b) = (stops[from][c] as f32, stops[from + 1][c] as f32);
+            (a + (b - a) * t).round() as u8
+        };
+
+        RulesetColour::Rgba(lerp(0), lerp(1), lerp(2), lerp(3))
+    }
+}
+
+/// Replaces `$reference` in a rule with `$replacement`, leaving longer names such as
+/// `$reference_2` untouched
+fn substitute_reference(rule: &str, reference: &str, replacement: &str) -> String {
+    let pattern = format!("${reference}");
+    let mut result = String::with_capacity(rule.len());
+    let mut rest = rule;
+
+    while let Some(index) = rest.find(&pattern) {
+        let after = &rest[index + pattern.len()..];
+        result.push_str(&rest[..index]);
+        if after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            result.push_str(&pattern);
+        } else {
+            result.push('$');
+            result.push_str(replacement);
+        }
+        rest = after;
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[derive(Clone, Debug)]
 pub struct RulesetState {
     pub colour: RulesetColour,
-    pub rules: HashMap<String, RulesetOutcome>,
+    pub rules: IndexMap<String, RulesetOutcome>,
     pub otherwise: Option<RulesetOutcome>,
-    parsed_rules: Vec<(Vec<Condition>, RulesetOutcome)>,
+    parsed_rules: Vec<(String, Vec<Condition>, RulesetOutcome)>,
+}
+
+#[derive(Deserialize)]
+struct RulesetStateDefinition {
+    colour: RulesetColour,
+    rules: IndexMap<String, RulesetOutcome>,
+    otherwise: Option<RulesetOutcome>,
 }
 
 impl<'de> Deserialize<'de> for RulesetState {
@@ -88,41 +426,55 @@ impl<'de> Deserialize<'de> for RulesetState {
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        struct RulesetStateHelper {
-            colour: RulesetColour,
-            rules: HashMap<String, RulesetOutcome>,
-            otherwise: Option<RulesetOutcome>,
+        RulesetState::new(RulesetStateDefinition::deserialize(deserializer)?).map_err(|errors| {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            de::Error::custom(messages.join("\n"))
+        })
+    }
+}
+
+impl RulesetState {
+    fn new(definition: RulesetStateDefinition) -> Result<Self, Vec<RulesetError>> {
+        match RulesetState::parse(definition) {
+            (state, errors) if errors.is_empty() => Ok(state),
+            (_, errors) => Err(errors),
         }
+    }
 
-        let helper = RulesetStateHelper::deserialize(deserializer)?;
+    /// Builds a state from its definition, keeping whichever rules parse successfully
+    fn parse(definition: RulesetStateDefinition) -> (Self, Vec<RulesetError>) {
         let mut state = RulesetState {
-            colour: helper.colour,
-            rules: helper.rules,
-            otherwise: helper.otherwise,
+            colour: definition.colour,
+            rules: definition.rules,
+            otherwise: definition.otherwise,
             parsed_rules: vec![],
         };
 
         // Run the parse_rules function after deserialization
-        state.parse_rules().map_err(de::Error::custom)?;
+        let errors = state.parse_rules().err().unwrap_or_default();
 
-        Ok(state)
+        (state, errors)
     }
-}
 
-impl RulesetState {
-    pub fn transition(&self, neighbours: &HashMap<String, usize>) -> Option<&RulesetOutcome> {
+    /// Each rule's source alongside its parsed conditions
+    pub fn parsed_rules(&self) -> impl Iterator<Item = (&str, &[Condition], &RulesetOutcome)> {
+        self.parsed_rules
+            .iter()
+            .map(|(source, conditions, outcome)| (source.as_str(), conditions.as_slice(), outcome))
+    }
+
+    pub fn transition(&self, neighbours: &Neighbours) -> Option<&RulesetOutcome> {
         let rules = &self.parsed_rules;
         // println!("{:?}", rules);
 
         rules
             .iter()
-            .find(|(conditions, _)| {
+            .find(|(_, conditions, _)| {
                 conditions.iter().all(|c| {
-                    let neighbour_value = neighbours.get(&c.state).unwrap_or(&0);
+                    let neighbour_value = &neighbours.count(&c.state);
 
                     let value: &usize = match &c.compare_to {
-                        CompareTo::State(name) => neighbours.get(name).unwrap_or(&0),
+                        CompareTo::State(state) => &neighbours.count(state),
                         CompareTo::Value(v) => v,
                     };
 
@@ -138,27 +490,36 @@ impl RulesetState {
                     }
                 })
             })
-            .map(|(_, o)| o)
+            .map(|(_, _, o)| o)
             .or(self.otherwise.as_ref())
     }
 
-    fn parse_rules(&mut self) -> Result<(), String> {
+    fn parse_rules(&mut self) -> Result<(), Vec<RulesetError>> {
         let mut rules = vec![];
+        let mut errors = vec![];
 
         for (s, out) in &self.rules {
-            let conditions = match parse_conditions(s) {
-                Ok((_, c)) => c,
-                Err(e) => return Err(format!("{:?}", e)),
-            };
-            rules.push((conditions, out.clone()))
+            match parse_rule(s) {
+                Ok(conditions) => rules.push((s.clone(), conditions, out.clone())),
+                Err(e) => errors.push(e.within(&format!("rules[{s:?}]"))),
+            }
         }
 
         self.parsed_rules = rules;
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 }
 
+/// Parses a whole rule, failing if anything is left over after the conditions
+fn parse_rule(rule: &str) -> Result<Vec<Condition>, RulesetError> {
+    parse_conditions(rule).map_err(|e| RulesetError::new("", e.message).at_column(e.column))
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub(crate) struct RulesetOutcome {
     pub next: String,
@@ -172,6 +533,39 @@ pub enum RulesetColour {
     Hex(String),
 }
 
+impl RulesetColour {
+    pub fn rgba(&self) -> [u8; 4] {
+        match self {
+            RulesetColour::Rgba(r, g, b, a) => [*r, *g, *b, *a],
+            RulesetColour::Hex(s) => {
+                if self.is_valid() {
+                    let r = u8::from_str_radix(&s[1..3], 16).unwrap_or(0);
+                    let g = u8::from_str_radix(&s[3..5], 16).unwrap_or(0);
+                    let b = u8::from_str_radix(&s[5..7], 16).unwrap_or(0);
+                    let a = s
+                        .get(7..9)
+                        .map_or(255, |a| u8::from_str_radix(a, 16).unwrap_or(0));
+                    [r, g, b, a]
+                } else {
+                    [0, 0, 0, 0]
+                }
+            }
+        }
+    }
+
+    /// Hex colours must be of the form `#RRGGBB` or `#RRGGBBAA`
+    pub fn is_valid(&self) -> bool {
+        match self {
+            RulesetColour::Rgba(..) => true,
+            RulesetColour::Hex(s) => {
+                (s.len() == 7 || s.len() == 9)
+                    && s.starts_with('#')
+                    && s[1..].chars().all(|c| c.is_ascii_hexdigit())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,7 +576,7 @@ mod tests {
         let json_data = json!({
             "colour": [255, 0, 0, 255],
             "rules": {
-                "state1 > 2": {
+                "$state1 > 2": {
                     "next": "state2",
                     "paint": "#00ffffc2"
                 }
@@ -193,10 +587,10 @@ mod tests {
             serde_json::from_value(json_data).expect("Deserialization failed");
 
         assert_eq!(deserialized.colour, RulesetColour::Rgba(255, 0, 0, 255));
-        assert!(deserialized.rules.contains_key("state1 > 2"));
-        assert_eq!(deserialized.rules["state1 > 2"].next, "state2");
+        assert!(deserialized.rules.contains_key("$state1 > 2"));
+        assert_eq!(deserialized.rules["$state1 > 2"].next, "state2");
         assert_eq!(
-            deserialized.rules["state1 > 2"].paint,
+            deserialized.rules["$state1 > 2"].paint,
             Some(RulesetColour::Hex("#00ffffc2".to_string()))
         );
     }
@@ -205,7 +599,7 @@ mod tests {
     fn test_rulesetstate_transition() {
         let mut state = RulesetState {
             colour: RulesetColour::Rgba(255, 0, 0, 255),
-            rules: HashMap::new(),
+            rules: IndexMap::new(),
             otherwise: None,
             parsed_rules: vec![],
         };
@@ -220,7 +614,14 @@ mod tests {
 
         state.parse_rules().expect("Failed to parse rules");
 
-        let neighbours = HashMap::from([("state1".to_string(), 3)]);
+        let counts = HashMap::from([("state1".to_string(), 3)]);
+        let groups = IndexMap::new();
+        let neighbours = Neighbours {
+            counts: &counts,
+            current: "state1",
+            default_state: "state2",
+            groups: &groups,
+        };
         let result = state.transition(&neighbours);
 
         assert!(result.is_some());
@@ -231,7 +632,7 @@ mod tests {
     fn test_rulesetstate_parse_rules_error() {
         let mut state = RulesetState {
             colour: RulesetColour::Rgba(255, 0, 0, 255),
-            rules: HashMap::new(),
+            rules: IndexMap::new(),
             otherwise: None,
             parsed_rules: vec![],
         };
@@ -259,4 +660,150 @@ mod tests {
             _ => panic!("Expected HEX colour"),
         }
     }
+
+    #[test]
+    fn test_family_expansion() {
+        let json_data = json!({
+            "default_state": "step_1",
+            "neighbourhood": { "Moore": { "range": 1, "include_self": false } },
+            "families": {
+                "step": {
+                    "count": 3,
+                    "colours": [[0, 0, 0, 255], [255, 255, 255, 255]],
+                    "rules": {
+                        "$next > 0": { "next": "$next" }
+                    },
+                    "otherwise": { "next": "$prev" }
+                }
+            }
+        });
+
+        let ruleset: Ruleset = serde_json::from_value(json_data).expect("Deserialization failed");
+
+        assert_eq!(ruleset.states.len(), 3);
+        assert_eq!(
+            ruleset.states["step_1"].colour,
+            RulesetColour::Rgba(0, 0, 0, 255)
+        );
+        assert_eq!(
+            ruleset.states["step_2"].colour,
+            RulesetColour::Rgba(128, 128, 128, 255)
+        );
+        assert!(ruleset.states["step_3"].rules.contains_key("$step_1 > 0"));
+        assert_eq!(ruleset.states["step_3"].rules["$step_1 > 0"].next, "step_1");
+        assert_eq!(
+            ruleset.states["step_1"].otherwise.as_ref().unwrap().next,
+            "step_3"
+        );
+    }
+
+    #[test]
+    fn test_cyclic_family() {
+        let ruleset: Ruleset = serde_json::from_str(include_str!("../rulesets/cyclic.json"))
+            .expect("Deserialization failed");
+
+        assert_eq!(ruleset.states.len(), 16);
+        assert_eq!(
+            ruleset.states["state_16"].rules["$state_1 > 0"].next,
+            "state_1"
+        );
+    }
+
+    #[test]
+    fn test_ruleset_wildcard_transitions() {
+        let json_data = json!({
+            "default_state": "dead",
+            "neighbourhood": { "Moore": { "range": 1, "include_self": false } },
+            "groups": { "alive": ["red", "blue"] },
+            "states": {
+                "dead": {
+                    "colour": [0, 0, 0, 0],
+                    "rules": {
+                        "$alive == 3 ; $red >= 2": { "next": "red" },
+                        "$* == 1": { "next": "blue" }
+                    }
+                },
+                "red": {
+                    "colour": [255, 0, 0, 255],
+                    "rules": { "$self == 0": { "next": "dead" } }
+                },
+                "blue": {
+                    "colour": [0, 0, 255, 255],
+                    "rules": { "$any_except(blue) == 8": { "next": "red" } }
+                }
+            }
+        });
+
+        let ruleset: Ruleset = serde_json::from_value(json_data).expect("Deserialization failed");
+        let next = |current: &str, counts: &[(&str, usize)]| {
+            let counts = counts.iter().map(|(s, c)| (s.to_string(), *c)).collect();
+            ruleset.transition(current, &counts).map(|o| o.next.clone())
+        };
+
+        assert_eq!(
+            next("dead", &[("red", 2), ("blue", 1), ("dead", 5)]),
+            Some("red".to_string())
+        );
+        assert_eq!(
+            next("dead", &[("blue", 1), ("dead", 7)]),
+            Some("blue".to_string())
+        );
+        assert_eq!(next("dead", &[("dead", 8)]), None);
+        assert_eq!(
+            next("red", &[("blue", 2), ("dead", 6)]),
+            Some("dead".to_string())
+        );
+        assert_eq!(next("red", &[("red", 1), ("dead", 7)]), None);
+        assert_eq!(
+            next("blue", &[("red", 2), ("dead", 6)]),
+            Some("red".to_string())
+        );
+    }
+
+    #[test]
+    fn test_group_clashing_state() {
+        let json_data = json!({
+            "default_state": "dead",
+            "neighbourhood": "Individual",
+            "groups": { "dead": ["dead"] },
+            "states": {
+                "dead": { "colour": [0, 0, 0, 0], "rules": {} }
+            }
+        });
+
+        assert!(serde_json::from_value::<Ruleset>(json_data).is_err());
+    }
+
+    #[test]
+    fn test_family_clashing_state() {
+        let json_data = json!({
+            "default_state": "step_1",
+            "neighbourhood": "Individual",
+            "states": {
+                "step_1": { "colour": [0, 0, 0, 0], "rules": {} }
+            },
+            "families": {
+                "step": { "count": 2, "colours": ["#000000ff"] }
+            }
+        });
+
+        assert!(serde_json::from_value::<Ruleset>(json_data).is_err());
+    }
+
+    #[test]
+    fn test_substitute_reference() {
+        assert_eq!(
+            substitute_reference("$next > 0 ; $next_2 == 1", "next", "s_3"),
+            "$s_3 > 0 ; $next_2 == 1"
+        );
+    }
+
+    #[test]
+    fn test_set_family_count() {
+        let json = r#"{ "families": { "step": { "count": 3, "colours": [] } } }"#;
+        let updated = set_family_count(json, "step", 5).expect("Update failed");
+        let value: serde_json::Value = serde_json::from_str(&updated).unwrap();
+        assert_eq!(value["families"]["step"]["count"], 5);
+        assert!(set_family_count(json, "missing", 5).is_err());
+    }
 }
@@ -0,0 +1,262 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{ruleset::Ruleset, BUNDLED_RULESETS};
+
+/// A ruleset that can be picked from the Defined Rules list
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct RulesetEntry {
+    pub name: String,
+    pub json: String,
+    /// The file the ruleset was read from, or `None` if it is built in
+    pub path: Option<PathBuf>,
+}
+
+/// An entry in the ruleset or pattern library, which files can add to or replace by name
+pub(crate) trait LibraryEntry: Sized {
+    fn name(&self) -> &str;
+
+    /// Reads an entry from a file, naming it after the file
+    fn load(path: &Path) -> Result<Self, String>;
+}
+
+impl LibraryEntry for RulesetEntry {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads and checks a ruleset file, naming it after the file
+    fn load(path: &Path) -> Result<RulesetEntry, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+
+        if let Err(errors) = Ruleset::from_json(&json) {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(format!("{}: {}", path.display(), errors.join(", ")));
+        }
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        Ok(RulesetEntry {
+            name: display_name(&stem),
+            json,
+            path: Some(path.to_path_buf()),
+        })
+    }
+}
+
+/// Where the user's own files live, such as `~/.config/game-of-life`
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| std::env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("game-of-life"))
+}
+
+/// Where the user's own rulesets live, such as `~/.config/game-of-life/rulesets`
+pub fn user_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("rulesets"))
+}
+
+/// The built-in rulesets followed by every valid ruleset in `dirs`, along with why any
+/// other `.json` files were skipped
+///
+/// A file with the same name as a built-in ruleset replaces it, and later directories take
+/// precedence over earlier ones.
+pub fn discover(dirs: &[PathBuf]) -> (Vec<RulesetEntry>, Vec<String>) {
+    let mut entries: Vec<RulesetEntry> = BUNDLED_RULESETS
+        .iter()
+        .map(|(name, json)| RulesetEntry {
+            name: name.to_string(),
+            json: json.to_string(),
+            path: None,
+        })
+        .collect();
+    let problems = load_files(&mut entries, dirs, &["json"]);
+    (entries, problems)
+}
+
+/// Loads every file in `dirs` with one of `extensions` into `entries` in name order,
+/// returning why any of them were skipped
+pub fn load_files<T: LibraryEntry>(
+    entries: &mut Vec<T>,
+    dirs: &[PathBuf],
+    extensions: &[&str],
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    for dir in dirs {
+        // Missing directories are normal, most users will not have made one
+        let Ok(files) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = files
+            .filter_map(|file| Some(file.ok()?.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|e| extensions.contains(&e.to_string_lossy().as_ref()))
+            })
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match T::load(&path) {
+                Ok(entry) => {
+                    add(entries, entry);
+                }
+                Err(e) => problems.push(e),
+            }
+        }
+    }
+
+    problems
+}
+
+/// Adds an entry to the list, replacing any with the same name, and returns its index
+pub fn add<T: LibraryEntry>(entries: &mut Vec<T>, entry: T) -> usize {
+    match entries.iter().position(|e| e.name() == entry.name()) {
+        Some(index) => {
+            entries[index] = entry;
+            index
+        }
+        None => {
+            entries.push(entry);
+            entries.len() - 1
+        }
+    }
+}
+
+/// Notices when a ruleset file is saved, by comparing its modification time each poll
+pub(crate) struct RulesetWatcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl RulesetWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        RulesetWatcher { path, modified }
+    }
+
+    /// The file's new contents if it has changed since it was last polled
+    pub fn poll(&mut self) -> Option<Result<String, String>> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+        Some(
+            fs::read_to_string(&self.path)
+                .map_err(|e| format!("Could not read {}: {e}", self.path.display())),
+        )
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Turns a file name like `game_of_life` into `Game of Life`
+pub fn display_name(stem: &str) -> String {
+    stem.split(['_', '-', ' '])
+        .filter(|word| !word.is_empty())
+        .enumerate()
+        .map(|(i, word)| match word {
+            "of" | "and" | "the" if i > 0 => word.to_string(),
+            _ => {
+                let mut chars = word.chars();
+                chars.next().map_or(String::new(), |first| {
+                    first.to_uppercase().chain(chars).collect()
+                })
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(display_name("game_of_life"), "Game of Life");
+        assert_eq!(display_name("wireworld"), "Wireworld");
+        assert_eq!(display_name("brians-brain"), "Brians Brain");
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = TempDir::new("ruleset-library");
+        let seeds = include_str!("../rulesets/highlife.json");
+        fs::write(dir.join("seeds.json"), seeds).unwrap();
+        fs::write(
+            dir.join("wireworld.json"),
+            include_str!("../rulesets/wireworld.json"),
+        )
+        .unwrap();
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        fs::write(dir.join("notes.txt"), "not a ruleset").unwrap();
+
+        let (entries, problems) = discover(&[dir.path().to_path_buf(), dir.join("missing")]);
+
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Game of Life",
+                "Highlife",
+                "Immigration",
+                "Cyclic",
+                "Wireworld",
+                "Seeds"
+            ]
+        );
+        assert_eq!(entries[4].path, Some(dir.join("wireworld.json")));
+        assert_eq!(entries[0].path, None);
+        assert_eq!(entries[5].json, seeds);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("broken.json"), "{problems:?}");
+
+        // A ruleset given on the command line replaces one of the same name too
+        let mut entries = entries;
+        let count = entries.len();
+        let highlife = RulesetEntry {
+            name: "Highlife".to_string(),
+            json: seeds.to_string(),
+            path: Some(PathBuf::from("highlife.json")),
+        };
+        assert_eq!(add(&mut entries, highlife.clone()), 1);
+        assert_eq!(entries.len(), count);
+        assert_eq!(entries[1], highlife);
+    }
+
+    #[test]
+    fn test_watcher() {
+        let dir = TempDir::new("ruleset-watch");
+        let path = dir.join("watched.json");
+        fs::write(&path, "first").unwrap();
+
+        let mut watcher = RulesetWatcher::new(path.clone());
+        assert_eq!(watcher.poll(), None);
+
+        fs::write(&path, "second").unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(watcher.poll(), Some(Ok("second".to_string())));
+        assert_eq!(watcher.poll(), None);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(watcher.poll(), Some(Err(_))));
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::world::World;
+
+/// Longest a run waiting for the world to settle goes before giving up
+const MAX_RUN: u64 = 100_000;
+
+/// What a run started from the UI waits for before pausing
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum StopWhen {
+    /// A number of generations have passed
+    Generations(u64),
+    /// No cell changes from one generation to the next
+    Stable,
+    /// The world returns to a state it was in earlier in the run
+    Periodic,
+    /// Every cell is in the default state
+    Empty,
+}
+
+/// Runs the world until something happens, then reports what
+pub(crate) struct Run {
+    stop: StopWhen,
+    started: u64,
+    /// Hashes of the states of every cell, with the generation they were seen at
+    seen: HashMap<u64, u64>,
+    previous: u64,
+}
+
+impl Run {
+    pub fn new(stop: StopWhen, world: &World) -> Self {
+        let hash = state_hash(world);
+        Run {
+            stop,
+            started: world.generation,
+            seen: HashMap::from([(hash, world.generation)]),
+            previous: hash,
+        }
+    }
+
+    /// Checks the world after each generation, returning why the run is over once it is
+    pub fn check(&mut self, world: &World) -> Option<String> {
+        // Going back in time or resetting can leave the world before the run's start
+        let elapsed = world.generation.saturating_sub(self.started);
+        match self.stop {
+            StopWhen::Generations(count) => {
+                return (elapsed >= count).then(|| format!("Ran {count} generations"));
+            }
+            StopWhen::Empty => {
+                if world.live_bounds().is_none() {
+                    return Some(format!("Empty at generation {}", world.generation));
+                }
+            }
+            StopWhen::Stable | StopWhen::Periodic => {
+                let hash = state_hash(world);
+                if self.stop == StopWhen::Stable && self.previous == hash {
+                    return Some(format!("Stable at generation {}", world.generation));
+                }
+                self.previous = hash;
+
+                if self.stop == StopWhen::Periodic {
+                    if let Some(seen) = self.seen.insert(hash, world.generation) {
+                        return Some(format!(
+                            "Period {} reached at generation {}",
+                            world.generation - seen,
+                            world.generation
+                        ));
+                    }
+                }
+            }
+        }
+
+        (elapsed >= MAX_RUN).then(|| format!("Gave up after {MAX_RUN} generations"))
+    }
+}
+
+/// Identifies the states of every cell, ignoring colour
+fn state_hash(world: &World) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for cell in &world.cells {
+        cell.state.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pattern::rle,
+        test_utils::{world, LIFE},
+    };
+
+    /// Runs a pattern until the run stops, or panics
+    fn run(pattern: &str, stop: StopWhen) -> String {
+        let mut world = world(LIFE, (10, 10), None);
+        world.stamp(&rle::parse(pattern).unwrap(), 3, 3).unwrap();
+
+        let mut run = Run::new(stop, &world);
+        for _ in 0..100 {
+            world.next_generation();
+            if let Some(reason) = run.check(&world) {
+                return reason;
+            }
+        }
+        panic!("Run never stopped");
+    }
+
+    #[test]
+    fn test_stop_conditions() {
+        let block = "x = 2, y = 2\n2o$2o!";
+        let blinker = "x = 3, y = 1\n3o!";
+        let single = "x = 1, y = 1\no!";
+
+        assert_eq!(run(block, StopWhen::Generations(7)), "Ran 7 generations");
+        assert_eq!(run(block, StopWhen::Stable), "Stable at generation 1");
+        assert_eq!(run(single, StopWhen::Empty), "Empty at generation 1");
+        assert_eq!(
+            run(blinker, StopWhen::Periodic),
+            "Period 2 reached at generation 2"
+        );
+        // An empty world is stable too, once it has been empty for a generation
+        assert_eq!(run(single, StopWhen::Stable), "Stable at generation 2");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{pattern::Pattern, world::World};
+
+/// A rectangle of cells dragged out between two corners, in either order
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Selection {
+    pub start: (isize, isize),
+    pub end: (isize, isize),
+}
+
+impl Selection {
+    pub fn new(cell: (isize, isize)) -> Self {
+        Selection {
+            start: cell,
+            end: cell,
+        }
+    }
+
+    /// The top left corner, width and height, with both corners included
+    pub fn rect(&self) -> (isize, isize, usize, usize) {
+        let (x, y) = (self.start.0.min(self.end.0), self.start.1.min(self.end.1));
+        let width = self.start.0.abs_diff(self.end.0) + 1;
+        let height = self.start.1.abs_diff(self.end.1) + 1;
+        (x, y, width, height)
+    }
+
+    /// Copies the selected cells into a pattern
+    pub fn copy(&self, world: &World) -> Pattern {
+        let (x, y, width, height) = self.rect();
+        world.extract(x, y, width, height)
+    }
+
+    /// Copies the selected cells into a pattern and clears them to the default state
+    pub fn cut(&self, world: &mut World) -> Pattern {
+        let pattern = self.copy(world);
+        self.clear(world);
+        pattern
+    }
+
+    /// Sets the selected cells to the default state
+    pub fn clear(&self, world: &mut World) {
+        let (x, y, width, height) = self.rect();
+        if let Some(cell) = world.state_cell(0) {
+            world.fill(x, y, width, height, &cell);
+        }
+    }
+
+    /// Replaces the selected cells with a transformed copy of them with the same top left
+    /// corner, returning the selection around the result
+    pub fn transform(
+        &self,
+        world: &mut World,
+        transform: impl FnOnce(&Pattern) -> Pattern,
+    ) -> Result<Selection, String> {
+        let (x, y, _, _) = self.rect();
+        let pattern = transform(&self.cut(world));
+        world.stamp(&pattern, x, y)?;
+        Ok(Selection {
+            start: (x, y),
+            end: (
+                x + pattern.width as isize - 1,
+                y + pattern.height as isize - 1,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pattern::rle,
+        test_utils::{world, LIFE},
+    };
+
+    #[test]
+    fn test_rect() {
+        assert_eq!(Selection::new((3, 4)).rect(), (3, 4, 1, 1));
+        assert_eq!(
+            Selection {
+                start: (5, 1),
+                end: (2, 6)
+            }
+            .rect(),
+            (2, 1, 4, 6)
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut world = world(LIFE, (8, 8), None);
+        // OO
+        // O.
+        // O.
+        let pattern = rle::parse("x = 2, y = 3\n2o$o$o!").unwrap();
+        world.stamp(&pattern, 1, 1).unwrap();
+        let selection = Selection {
+            start: (2, 3),
+            end: (1, 1),
+        };
+
+        let rotated = selection.transform(&mut world, Pattern::rotated).unwrap();
+        assert_eq!(rotated.rect(), (1, 1, 3, 2));
+        assert_eq!(rotated.copy(&world), pattern.rotated());
+        assert_eq!(world.live_bounds(), Some((1, 1, 3, 2)));
+
+        let cut = rotated.cut(&mut world);
+        assert_eq!(cut, pattern.rotated());
+        assert_eq!(world.live_bounds(), None);
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, Spawn},
+    pattern::MAX_CELLS,
+    ruleset::Ruleset,
+    world::World,
+};
+
+/// Everything needed to carry on a simulation exactly where it was saved
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub(crate) struct Snapshot {
+    /// The ruleset JSON, as written in the config
+    pub ruleset: String,
+    pub width: usize,
+    pub height: usize,
+    pub generation: u64,
+    pub seed: u64,
+    /// Periodic spawns made before the snapshot, so later ones land in the same places
+    #[serde(default)]
+    pub spawns: u64,
+    /// Spray and periodic spawn settings, including how long until the next spawn
+    #[serde(default)]
+    pub spawn: Spawn,
+    pub paused: bool,
+    pub step_time: f32,
+    #[serde(default = "default_generations_per_step")]
+    pub generations_per_step: f32,
+    pub cell_size: f32,
+    /// Each distinct state and colour in the world, since `paint` can colour a cell
+    /// differently to its state
+    pub palette: Vec<(String, [u8; 4])>,
+    /// Index into the palette of each cell, row by row
+    pub cells: Vec<usize>,
+}
+
+impl Snapshot {
+    pub fn capture(world: &World, config: &Config) -> Self {
+        let (palette, cells) = world.palette();
+
+        Snapshot {
+            ruleset: config.ruleset.clone(),
+            width: world.width,
+            height: world.height,
+            generation: world.generation,
+            seed: config.seed,
+            spawns: config.spawns,
+            spawn: config.spawn.clone(),
+            paused: config.paused,
+            step_time: config.step_time,
+            generations_per_step: config.generations_per_step,
+            cell_size: config.cell_size,
+            palette,
+            cells,
+        }
+    }
+
+    /// Rebuilds the world and copies the saved settings into `config`
+    pub fn restore(&self, config: &mut Config) -> Result<World, String> {
+        let ruleset = Ruleset::from_json(&self.ruleset).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("Invalid ruleset: {}", errors.join(", "))
+        })?;
+
+        // Checked before the world is allocated, so a bad snapshot can't exhaust memory
+        let cells = self
+            .width
+            .checked_mul(self.height)
+            .filter(|&cells| cells > 0 && cells <= MAX_CELLS)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid world size {}x{}, expected at most {MAX_CELLS} cells",
+                    self.width, self.height
+                )
+            })?;
+        if self.cells.len() != cells {
+            return Err(format!(
+                "Expected {cells} cells for a {}x{} world, found {}",
+                self.width,
+                self.height,
+                self.cells.len()
+            ));
+        }
+
+        let mut world =
+            World::new((self.width, self.height), ruleset).ok_or("Ruleset has no default state")?;
+        world.set_palette_cells(&self.palette, &self.cells)?;
+        world.generation = self.generation;
+
+        config.ruleset = self.ruleset.clone();
+        config.seed = self.seed;
+        config.spawns = self.spawns;
+        config.spawn = self.spawn.clone();
+        config.paused = self.paused;
+        config.step_time = self.step_time;
+        config.generations_per_step = self.generations_per_step;
+        config.cell_size = self.cell_size;
+        config.grid_size = (self.width, self.height);
+        rand::srand(self.seed);
+
+        Ok(world)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Could not write {path}: {e}"))
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid snapshot {path}: {e}"))
+    }
+}
+
+fn default_generations_per_step() -> f32 {
+    Config::default().generations_per_step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pattern::rle, test_utils::world};
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config {
+            ruleset: include_str!("../rulesets/wireworld.json").to_string(),
+            seed: 1234,
+            step_time: 0.25,
+            generations_per_step: 4.0,
+            spawn: Spawn {
+                timer_size: 3.,
+                timer: 2.5,
+                spawn: true,
+                spawn_state: 2,
+                ..Spawn::default()
+            },
+            ..Config::default()
+        };
+        let mut world = world(&config.ruleset, (12, 6), None);
+        let circuit = rle::parse("x = 8, y = 3\n8C$ABC2.3C$8C!").unwrap();
+        world.stamp(&circuit, 2, 1).unwrap();
+        for _ in 0..3 {
+            world.next_generation();
+        }
+
+        let json = serde_json::to_string(&Snapshot::capture(&world, &config)).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+
+        let mut restored_config = Config::default();
+        let mut restored = snapshot.restore(&mut restored_config).unwrap();
+
+        assert_eq!(restored_config.ruleset, config.ruleset);
+        assert_eq!(restored_config.seed, 1234);
+        assert_eq!(restored_config.step_time, 0.25);
+        assert_eq!(restored_config.generations_per_step, 4.0);
+        assert_eq!(restored_config.spawn, config.spawn);
+        assert_eq!((restored.width, restored.height), (12, 6));
+        assert_eq!(restored_config.grid_size, (12, 6));
+        assert_eq!(restored.generation, 3);
+        assert_eq!(restored.cells, world.cells);
+
+        world.next_generation();
+        restored.next_generation();
+        assert_eq!(restored.cells, world.cells);
+        assert_eq!(restored.generation, 4);
+
+        // Periodic spawns carry on from where the snapshot left off
+        config.spawns = 7;
+        let snapshot = Snapshot::capture(&world, &config);
+        let mut restored = snapshot.restore(&mut restored_config).unwrap();
+        assert_eq!(restored_config.spawns, 7);
+        for _ in 0..3 {
+            for (world, config) in [
+                (&mut world, &mut config),
+                (&mut restored, &mut restored_config),
+            ] {
+                world.spawn_random(4, "head", 0.5, config.seed, config.spawns);
+                config.spawns += 1;
+                world.next_generation();
+            }
+            assert_eq!(restored.cells, world.cells);
+        }
+
+        config.paused = true;
+        assert!(Snapshot::capture(&restored, &config).paused);
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let config = Config::default();
+        let world = world(&config.ruleset, (2, 2), None);
+        let snapshot = Snapshot::capture(&world, &config);
+
+        let mut truncated = Snapshot::capture(&world, &config);
+        truncated.cells.pop();
+        assert!(truncated.restore(&mut Config::default()).is_err());
+
+        let mut unknown = Snapshot::capture(&world, &config);
+        unknown.palette[0].0 = "zombie".to_string();
+        assert!(unknown.restore(&mut Config::default()).is_err());
+
+        let mut out_of_range = Snapshot::capture(&world, &config);
+        out_of_range.cells[0] = 5;
+        assert!(out_of_range.restore(&mut Config::default()).is_err());
+
+        // Sizes are checked before anything is allocated
+        for (width, height) in [(0, 0), (usize::MAX, 2), (1 << 16, 1 << 16)] {
+            let huge = Snapshot {
+                width,
+                height,
+                ..Snapshot::capture(&world, &config)
+            };
+            assert!(huge.restore(&mut Config::default()).is_err());
+        }
+
+        // Snapshots saved before spawn settings were kept still load
+        let mut json: serde_json::Value = serde_json::to_value(&snapshot).unwrap();
+        json.as_object_mut().unwrap().remove("spawn");
+        json.as_object_mut().unwrap().remove("generations_per_step");
+        let old: Snapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(old.spawn, Spawn::default());
+        assert_eq!(old.generations_per_step, 1.0);
+    }
+}
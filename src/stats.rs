@@ -0,0 +1,211 @@
+use std::collections::{HashMap, VecDeque};
+
+use macroquad::prelude::*;
+
+use crate::world::World;
+
+/// How many samples the population graph shows
+const GRAPH_SAMPLES: usize = 300;
+/// Seconds of history the generation rate is averaged over
+const RATE_WINDOW: f64 = 1.0;
+
+/// How many cells are in each state, in the order of `Ruleset::state_names`
+pub fn populations(world: &World) -> Vec<usize> {
+    let names = world.ruleset.state_names();
+    let indices: HashMap<&str, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, &name)| (name, i))
+        .collect();
+    let mut counts = vec![0; names.len()];
+    for cell in &world.cells {
+        if let Some(&i) = indices.get(cell.state.as_str()) {
+            counts[i] += 1;
+        }
+    }
+    counts
+}
+
+/// Populations and speed of the simulation over recent frames, for the on screen overlay
+pub(crate) struct Stats {
+    /// Populations each time the world changed, oldest first
+    samples: VecDeque<Vec<usize>>,
+    /// (time, generation) for recent frames, to work out generations per second
+    times: VecDeque<(f64, u64)>,
+    revision: Option<u64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            samples: VecDeque::new(),
+            times: VecDeque::new(),
+            revision: None,
+        }
+    }
+
+    /// Takes a sample of the world, `time` being in seconds
+    pub fn record(&mut self, world: &World, time: f64) {
+        // Going back in time makes the rate meaningless
+        if self
+            .times
+            .back()
+            .is_some_and(|(_, generation)| *generation > world.generation)
+        {
+            self.times.clear();
+        }
+        self.times.push_back((time, world.generation));
+        while self
+            .times
+            .front()
+            .is_some_and(|(start, _)| time - start > RATE_WINDOW)
+        {
+            self.times.pop_front();
+        }
+
+        if self.revision == Some(world.revision) {
+            return;
+        }
+        self.revision = Some(world.revision);
+
+        let populations = populations(world);
+        if self
+            .samples
+            .back()
+            .is_some_and(|last| last.len() != populations.len())
+        {
+            self.samples.clear();
+        }
+        self.samples.push_back(populations);
+        if self.samples.len() > GRAPH_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Generations per second over the last second or so
+    pub fn rate(&self) -> f64 {
+        match (self.times.front(), self.times.back()) {
+            (Some((start, first)), Some((end, last))) if end > start => {
+                (last - first) as f64 / (end - start)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Draws the generation, rate, each state's population and a graph of the populations of
+    /// every state but the default one, with its top right corner at (right, top)
+    pub fn draw(&self, world: &World, right: f32, top: f32) {
+        let names = world.ruleset.state_names();
+        let colours: Vec<Color> = (0..names.len())
+            .map(|i| match world.state_cell(i) {
+                // Transparent states look like empty space, so are shown in grey
+                Some(cell) if cell.colour.a > 0.0 => Color {
+                    a: 1.0,
+                    ..cell.colour
+                },
+                _ => GRAY,
+            })
+            .collect();
+        let current = self.samples.back();
+
+        let width = 260.0;
+        let line = 18.0;
+        let graph_height = 80.0;
+        let height = line * (2 + names.len()) as f32 + graph_height + 16.0;
+        let left = right - width;
+        draw_rectangle(left, top, width, height, Color::new(0.0, 0.0, 0.0, 0.75));
+
+        let mut y = top + line;
+        draw_text(
+            &format!("Generation {}", world.generation),
+            left + 8.0,
+            y,
+            20.0,
+            WHITE,
+        );
+        y += line;
+        draw_text(
+            &format!("{:.1} generations/s", self.rate()),
+            left + 8.0,
+            y,
+            20.0,
+            WHITE,
+        );
+        for (i, name) in names.iter().enumerate() {
+            y += line;
+            let count = current.and_then(|c| c.get(i)).copied().unwrap_or(0);
+            draw_text(&format!("{name}: {count}"), left + 8.0, y, 20.0, colours[i]);
+        }
+
+        // Each line is scaled to the highest population shown, so small ones are still visible
+        let graph = Rect::new(left + 8.0, y + 8.0, width - 16.0, graph_height);
+        draw_rectangle_lines(graph.x, graph.y, graph.w, graph.h, 1.0, DARKGRAY);
+        let max = self
+            .samples
+            .iter()
+            .flat_map(|sample| sample.iter().skip(1))
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let step = graph.w / (GRAPH_SAMPLES - 1) as f32;
+        for (state, colour) in colours.iter().enumerate().skip(1) {
+            let points: Vec<Vec2> = self
+                .samples
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| {
+                    let value = sample.get(state).copied().unwrap_or(0) as f32 / max as f32;
+                    vec2(graph.x + i as f32 * step, graph.bottom() - value * graph.h)
+                })
+                .collect();
+            for pair in points.windows(2) {
+                draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, 1.5, *colour);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{world, WIREWORLD};
+
+    const WIRE: &str = "x = 4, y = 1\nABCC!";
+
+    #[test]
+    fn test_populations() {
+        assert_eq!(
+            populations(&world(WIREWORLD, (5, 5), Some(WIRE))),
+            vec![21, 1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut world = world(WIREWORLD, (5, 5), Some(WIRE));
+        let mut stats = Stats::new();
+        stats.record(&world, 0.0);
+        assert_eq!(stats.rate(), 0.0);
+
+        // Unchanged worlds aren't sampled again, but still count towards the rate
+        stats.record(&world, 0.1);
+        assert_eq!(stats.samples.len(), 1);
+        for i in 1..=10 {
+            world.next_generation();
+            stats.record(&world, 0.1 + i as f64 * 0.05);
+        }
+        assert_eq!(stats.samples.len(), 11);
+        assert!((stats.rate() - 10.0 / 0.6).abs() < 1e-9);
+
+        // Only the last second counts
+        stats.record(&world, 2.0);
+        assert_eq!(stats.rate(), 0.0);
+
+        for _ in 0..GRAPH_SAMPLES {
+            world.next_generation();
+            stats.record(&world, 2.0);
+        }
+        assert_eq!(stats.samples.len(), GRAPH_SAMPLES);
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{pattern::rle, ruleset::Ruleset, world::World};
+
+pub const LIFE: &str = include_str!("../rulesets/game_of_life.json");
+pub const WIREWORLD: &str = include_str!("../rulesets/wireworld.json");
+
+/// A world of `size` running the ruleset in `json`, with an RLE pattern placed in its top
+/// left corner if one is given
+pub fn world(json: &str, size: (usize, usize), pattern: Option<&str>) -> World {
+    let ruleset = Ruleset::from_json(json).expect("Ruleset should be valid");
+    let mut world = World::new(size, ruleset).expect("World should be valid");
+    if let Some(pattern) = pattern {
+        let pattern = rle::parse(pattern).expect("Pattern should be valid");
+        world.stamp(&pattern, 0, 0).expect("Pattern should fit");
+    }
+    world
+}
+
+/// An empty directory of its own under the system's temporary directory, removed along
+/// with everything in it when dropped, even if the test fails
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("game-of-life-{name}-{}", std::process::id()));
+        // Left behind by a run which was killed
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Temporary directory should be created");
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
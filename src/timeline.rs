@@ -0,0 +1,343 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use crate::world::{Cell, World};
+
+/// How many generations are kept to go back to
+const MAX_GENERATIONS: usize = 1000;
+/// Most memory the kept frames take, so big worlds keep fewer generations
+const MAX_BYTES: usize = 64 << 20;
+/// Every this many generations is kept whole, so that going back only replays a few diffs
+const KEYFRAME_INTERVAL: usize = 50;
+
+enum FrameCells {
+    /// Every cell, as an index into the timeline's palette
+    Key(Vec<u16>),
+    /// The cells which changed since the previous frame, with their new palette indices
+    Diff(Vec<(u32, u16)>),
+}
+
+impl FrameCells {
+    fn bytes(&self) -> usize {
+        match self {
+            FrameCells::Key(cells) => cells.len() * size_of::<u16>(),
+            FrameCells::Diff(changed) => changed.len() * size_of::<(u32, u16)>(),
+        }
+    }
+}
+
+struct Frame {
+    generation: u64,
+    cells: FrameCells,
+}
+
+/// A bounded record of the world's past generations, for stepping backwards and scrubbing
+pub(crate) struct Timeline {
+    frames: VecDeque<Frame>,
+    /// The size of the world every frame belongs to
+    size: (usize, usize),
+    /// Each distinct cell seen since the timeline was cleared, which frames index into
+    palette: Vec<Cell>,
+    /// The cells of the latest frame as palette indices, to diff the next generation against
+    last: Vec<u16>,
+    /// Memory taken by every frame's cells
+    bytes: usize,
+    max_bytes: usize,
+    /// The world revision of the latest frame, so unchanged worlds aren't recorded twice
+    revision: Option<u64>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Timeline {
+            frames: VecDeque::new(),
+            size: (0, 0),
+            palette: vec![],
+            last: vec![],
+            bytes: 0,
+            max_bytes: MAX_BYTES,
+            revision: None,
+        }
+    }
+
+    /// Forgets every generation, as when the world's ruleset changes
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.palette.clear();
+        self.last.clear();
+        self.bytes = 0;
+        self.revision = None;
+    }
+
+    /// The oldest and newest generations which can be gone back to
+    pub fn range(&self) -> Option<(u64, u64)> {
+        Some((
+            self.frames.front()?.generation,
+            self.frames.back()?.generation,
+        ))
+    }
+
+    /// Records the world as it is now. Any kept generations from its current one onwards are
+    /// replaced, so editing or stepping after going back starts a new future.
+    pub fn record(&mut self, world: &World) {
+        if self.revision == Some(world.revision) {
+            return;
+        }
+        self.revision = Some(world.revision);
+
+        if self.size != (world.width, world.height) {
+            self.clear();
+            self.size = (world.width, world.height);
+        }
+
+        let kept = self
+            .frames
+            .iter()
+            .position(|frame| frame.generation >= world.generation)
+            .unwrap_or(self.frames.len());
+        if kept < self.frames.len() {
+            self.frames.truncate(kept);
+            self.bytes = self.frames.iter().map(|frame| frame.cells.bytes()).sum();
+            self.last = self
+                .range()
+                .and_then(|(_, newest)| self.cells_at(newest))
+                .unwrap_or_default();
+        }
+
+        // Bring the latest cells up to date in place, noting which ones changed
+        let whole = self.last.len() != world.cells.len();
+        if whole {
+            self.last = vec![0; world.cells.len()];
+        }
+        let mut changed = vec![];
+        for (i, cell) in world.cells.iter().enumerate() {
+            if !whole && self.palette[self.last[i] as usize] == *cell {
+                continue;
+            }
+            let Some(index) = self.palette_index(cell) else {
+                // Too many distinct colours to index, so give up on the world's history
+                self.clear();
+                return;
+            };
+            self.last[i] = index;
+            changed.push((i as u32, index));
+        }
+
+        let since_key = self
+            .frames
+            .iter()
+            .rev()
+            .position(|frame| matches!(frame.cells, FrameCells::Key(_)));
+        let cells = match since_key {
+            Some(since_key) if !whole && since_key + 1 < KEYFRAME_INTERVAL => {
+                FrameCells::Diff(changed)
+            }
+            _ => FrameCells::Key(self.last.clone()),
+        };
+        self.bytes += cells.bytes();
+        self.frames.push_back(Frame {
+            generation: world.generation,
+            cells,
+        });
+
+        // Drop the oldest keyframe and its diffs together, so what's left starts with a keyframe
+        while self.frames.len() > MAX_GENERATIONS || self.bytes > self.max_bytes {
+            let Some(next_key) = self
+                .frames
+                .iter()
+                .skip(1)
+                .position(|frame| matches!(frame.cells, FrameCells::Key(_)))
+            else {
+                break;
+            };
+            for frame in self.frames.drain(..=next_key) {
+                self.bytes -= frame.cells.bytes();
+            }
+        }
+    }
+
+    /// The palette index of a cell, adding it to the palette if it is new
+    fn palette_index(&mut self, cell: &Cell) -> Option<u16> {
+        match self.palette.iter().position(|c| c == cell) {
+            Some(index) => Some(index as u16),
+            None if self.palette.len() <= u16::MAX as usize => {
+                self.palette.push(cell.clone());
+                Some((self.palette.len() - 1) as u16)
+            }
+            None => None,
+        }
+    }
+
+    /// The cells of a kept generation as palette indices, rebuilt from the keyframe before it
+    fn cells_at(&self, generation: u64) -> Option<Vec<u16>> {
+        let index = self
+            .frames
+            .iter()
+            .position(|frame| frame.generation == generation)?;
+        let key = self
+            .frames
+            .range(..=index)
+            .rposition(|frame| matches!(frame.cells, FrameCells::Key(_)))?;
+
+        let mut cells = vec![];
+        for frame in self.frames.range(key..=index) {
+            match &frame.cells {
+                FrameCells::Key(key_cells) => cells = key_cells.clone(),
+                FrameCells::Diff(changed) => {
+                    for &(i, cell) in changed {
+                        cells[i as usize] = cell;
+                    }
+                }
+            }
+        }
+        Some(cells)
+    }
+
+    /// Puts the world back to a kept generation, returning false if it isn't kept. Later
+    /// generations are kept until the world is next recorded.
+    pub fn restore(&mut self, world: &mut World, generation: u64) -> bool {
+        if (world.width, world.height) != self.size {
+            return false;
+        }
+        let Some(cells) = self.cells_at(generation) else {
+            return false;
+        };
+
+        world.cells = cells
+            .iter()
+            .map(|&i| self.palette[i as usize].clone())
+            .collect();
+        world.generation = generation;
+        world.touch();
+        self.revision = Some(world.revision);
+        true
+    }
+
+    /// Puts the world back to the generation before its current one, if it is kept
+    pub fn step_back(&mut self, world: &mut World) -> bool {
+        world
+            .generation
+            .checked_sub(1)
+            .is_some_and(|generation| self.restore(world, generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, LIFE};
+
+    fn world(size: (usize, usize)) -> World {
+        let mut world = test_utils::world(LIFE, size, None);
+        world.randomise();
+        world
+    }
+
+    /// Runs and records the world, returning its cells at each generation
+    fn run(world: &mut World, timeline: &mut Timeline, generations: usize) -> Vec<Vec<Cell>> {
+        let mut history = vec![];
+        timeline.record(world);
+        for _ in 0..generations {
+            history.push(world.cells.clone());
+            world.next_generation();
+            timeline.record(world);
+        }
+        history.push(world.cells.clone());
+        history
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut world = world((12, 12));
+        let mut timeline = Timeline::new();
+        let history = run(&mut world, &mut timeline, KEYFRAME_INTERVAL * 2 + 5);
+
+        assert_eq!(
+            timeline.range(),
+            Some((0, KEYFRAME_INTERVAL as u64 * 2 + 5))
+        );
+        for generation in [0, 1, 49, 50, 51, 104, 105, 3] {
+            assert!(timeline.restore(&mut world, generation));
+            assert_eq!(world.generation, generation);
+            assert_eq!(world.cells, history[generation as usize]);
+        }
+        assert!(!timeline.restore(&mut world, 106));
+        assert!(timeline.step_back(&mut world));
+        assert_eq!(world.cells, history[2]);
+
+        // Stepping on after going back replaces the old future
+        timeline.restore(&mut world, 10);
+        world.set_cell(0, 0, world.state_cell(1).unwrap());
+        let edited = world.cells.clone();
+        world.next_generation();
+        timeline.record(&world);
+        assert_eq!(timeline.range(), Some((0, 11)));
+        assert!(timeline.restore(&mut world, 9));
+        assert_eq!(world.cells, history[9]);
+        assert!(timeline.restore(&mut world, 10));
+        assert_eq!(world.cells, history[10]);
+        assert_ne!(world.cells, edited);
+    }
+
+    #[test]
+    fn test_edits_are_recorded() {
+        let mut world = world((8, 8));
+        let mut timeline = Timeline::new();
+        run(&mut world, &mut timeline, 3);
+
+        world.reset();
+        timeline.record(&world);
+        assert_eq!(timeline.range(), Some((0, 0)));
+
+        world.set_cell(2, 2, world.state_cell(1).unwrap());
+        let edited = world.cells.clone();
+        timeline.record(&world);
+        world.next_generation();
+        timeline.record(&world);
+        assert!(timeline.restore(&mut world, 0));
+        assert_eq!(world.cells, edited);
+
+        world.resize((9, 9), (0, 0));
+        timeline.record(&world);
+        assert_eq!(timeline.range(), Some((0, 0)));
+        assert_eq!(timeline.cells_at(0).unwrap().len(), 81);
+    }
+
+    #[test]
+    fn test_bounded_by_memory() {
+        let mut world = world((20, 20));
+        let mut timeline = Timeline::new();
+        // Room for about three keyframes and their diffs
+        timeline.max_bytes = 20 * 20 * 2 * 3;
+        let history = run(&mut world, &mut timeline, KEYFRAME_INTERVAL * 5);
+
+        let (oldest, newest) = timeline.range().unwrap();
+        assert!(timeline.bytes <= timeline.max_bytes);
+        assert_eq!(
+            timeline.bytes,
+            timeline
+                .frames
+                .iter()
+                .map(|f| f.cells.bytes())
+                .sum::<usize>()
+        );
+        assert!(newest - oldest < KEYFRAME_INTERVAL as u64 * 3);
+        assert!(timeline.restore(&mut world, oldest));
+        assert_eq!(world.cells, history[oldest as usize]);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut world = world((4, 4));
+        let mut timeline = Timeline::new();
+        let generations = MAX_GENERATIONS + KEYFRAME_INTERVAL * 2 + 10;
+        let history = run(&mut world, &mut timeline, generations);
+
+        let (oldest, newest) = timeline.range().unwrap();
+        assert_eq!(newest, generations as u64);
+        assert!(newest - oldest < MAX_GENERATIONS as u64);
+        assert!(newest - oldest >= (MAX_GENERATIONS - KEYFRAME_INTERVAL) as u64);
+        assert!(timeline.restore(&mut world, oldest));
+        assert_eq!(world.cells, history[oldest as usize]);
+        assert!(!timeline.restore(&mut world, oldest - 1));
+    }
+}
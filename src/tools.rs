@@ -0,0 +1,209 @@
+/// What dragging with the left mouse button does to the world
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Tool {
+    Select,
+    Pencil,
+    Line,
+    Rectangle,
+    FilledRectangle,
+    FloodFill,
+    Eraser,
+    Spray,
+}
+
+impl Tool {
+    /// Every tool, in the order they are listed and numbered in the UI
+    pub const ALL: [Tool; 8] = [
+        Tool::Select,
+        Tool::Pencil,
+        Tool::Line,
+        Tool::Rectangle,
+        Tool::FilledRectangle,
+        Tool::FloodFill,
+        Tool::Eraser,
+        Tool::Spray,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Select => "Select",
+            Tool::Pencil => "Pencil",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rectangle",
+            Tool::FilledRectangle => "Filled Rectangle",
+            Tool::FloodFill => "Flood Fill",
+            Tool::Eraser => "Eraser",
+            Tool::Spray => "Spray",
+        }
+    }
+
+    /// The cells of the shape dragged out between two cells, or None for tools which are
+    /// not shapes
+    pub fn shape(&self, start: (isize, isize), end: (isize, isize)) -> Option<Vec<(isize, isize)>> {
+        match self {
+            Tool::Line => Some(line(start, end)),
+            Tool::Rectangle => Some(rectangle(start, end, false)),
+            Tool::FilledRectangle => Some(rectangle(start, end, true)),
+            _ => None,
+        }
+    }
+
+    /// The shape dragged out between two cells as (x, y, width, height) rectangles of cells,
+    /// clipped to a world of `size`, so it can be previewed without drawing every cell
+    pub fn shape_rects(
+        &self,
+        start: (isize, isize),
+        end: (isize, isize),
+        (width, height): (isize, isize),
+    ) -> Vec<(isize, isize, isize, isize)> {
+        // Takes inclusive corners
+        let clip = |(min_x, min_y, max_x, max_y): (isize, isize, isize, isize)| {
+            let (min_x, min_y) = (min_x.max(0), min_y.max(0));
+            let (max_x, max_y) = (max_x.min(width - 1), max_y.min(height - 1));
+            (min_x <= max_x && min_y <= max_y)
+                .then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+        };
+        let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+        let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+
+        let corners = match self {
+            Tool::Line => line(start, end)
+                .into_iter()
+                .map(|(x, y)| (x, y, x, y))
+                .collect(),
+            // Outlines too thin to have a middle are the same as filled rectangles
+            Tool::FilledRectangle => vec![(min_x, min_y, max_x, max_y)],
+            Tool::Rectangle if max_x - min_x < 2 || max_y - min_y < 2 => {
+                vec![(min_x, min_y, max_x, max_y)]
+            }
+            Tool::Rectangle => vec![
+                (min_x, min_y, max_x, min_y),
+                (min_x, max_y, max_x, max_y),
+                (min_x, min_y + 1, min_x, max_y - 1),
+                (max_x, min_y + 1, max_x, max_y - 1),
+            ],
+            _ => vec![],
+        };
+        corners.into_iter().filter_map(clip).collect()
+    }
+}
+
+/// The cells on a straight line between two cells, both included
+pub fn line(start: (isize, isize), end: (isize, isize)) -> Vec<(isize, isize)> {
+    // Bresenham's algorithm, working for lines in any direction
+    let (dx, dy) = ((end.0 - start.0).abs(), -(end.1 - start.1).abs());
+    let step = ((end.0 - start.0).signum(), (end.1 - start.1).signum());
+    let mut error = dx + dy;
+    let mut cell = start;
+    let mut cells = vec![cell];
+
+    while cell != end {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            cell.0 += step.0;
+        }
+        if doubled <= dx {
+            error += dx;
+            cell.1 += step.1;
+        }
+        cells.push(cell);
+    }
+
+    cells
+}
+
+/// The cells of a rectangle with two cells as opposite corners, either just its edges or
+/// all of it
+pub fn rectangle(start: (isize, isize), end: (isize, isize), filled: bool) -> Vec<(isize, isize)> {
+    let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+    let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+
+    (min_y..=max_y)
+        .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+        .filter(|&(x, y)| filled || x == min_x || x == max_x || y == min_y || y == max_y)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shape_rects() {
+        let size = (10, 10);
+        assert_eq!(
+            Tool::FilledRectangle.shape_rects((-5, 2), (3, 20), size),
+            vec![(0, 2, 4, 8)]
+        );
+        assert_eq!(
+            Tool::Rectangle.shape_rects((2, 2), (5, 4), size),
+            vec![(2, 2, 4, 1), (2, 4, 4, 1), (2, 3, 1, 1), (5, 3, 1, 1)]
+        );
+        // Edges outside the world are left out
+        assert_eq!(
+            Tool::Rectangle.shape_rects((-2, 8), (4, 12), size),
+            vec![(0, 8, 5, 1), (4, 9, 1, 1)]
+        );
+        assert_eq!(
+            Tool::Line.shape_rects((8, 0), (11, 0), size),
+            vec![(8, 0, 1, 1), (9, 0, 1, 1)]
+        );
+        assert!(Tool::Pencil.shape_rects((0, 0), (1, 1), size).is_empty());
+
+        // Every cell of the shape is covered exactly once
+        for tool in [Tool::Line, Tool::Rectangle, Tool::FilledRectangle] {
+            let (start, end) = ((1, 7), (6, 2));
+            let mut covered: Vec<(isize, isize)> = tool
+                .shape_rects(start, end, size)
+                .into_iter()
+                .flat_map(|(x, y, w, h)| {
+                    (y..y + h).flat_map(move |y| (x..x + w).map(move |x| (x, y)))
+                })
+                .collect();
+            let mut cells = tool.shape(start, end).unwrap();
+            covered.sort();
+            cells.sort();
+            assert_eq!(covered, cells);
+        }
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(line((2, 2), (2, 2)), vec![(2, 2)]);
+        assert_eq!(line((0, 0), (3, 0)), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line((0, 0), (-2, -2)), vec![(0, 0), (-1, -1), (-2, -2)]);
+        assert_eq!(
+            line((0, 0), (4, 2)),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+
+        // Lines are unbroken whichever way they are drawn
+        for end in [(5, -3), (-1, 7), (-6, -2)] {
+            let cells = line((0, 0), end);
+            assert_eq!(cells.last(), Some(&end));
+            assert!(cells
+                .windows(2)
+                .all(|w| w[0].0.abs_diff(w[1].0) <= 1 && w[0].1.abs_diff(w[1].1) <= 1));
+        }
+    }
+
+    #[test]
+    fn test_rectangle() {
+        assert_eq!(rectangle((2, 1), (0, 0), true).len(), 6);
+        assert_eq!(
+            rectangle((0, 0), (2, 2), false),
+            vec![
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (0, 1),
+                (2, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2)
+            ]
+        );
+        assert_eq!(Tool::Pencil.shape((0, 0), (1, 1)), None);
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::rule_parsing::{parse_conditions, CompareTo, Condition, StateRef};
+use crate::ruleset::{Ruleset, RulesetColour, RulesetOutcome};
+
+/// A problem with a ruleset, located by its JSON path and, for rules, the column within
+/// the rule string
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct RulesetError {
+    pub path: String,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl RulesetError {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn at_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Prefixes the path with that of the enclosing JSON object
+    pub fn within(mut self, parent: &str) -> Self {
+        self.path = match (parent.is_empty(), self.path.is_empty()) {
+            (true, _) => self.path,
+            (false, true) => parent.to_string(),
+            (false, false) if self.path.starts_with('[') => format!("{parent}{}", self.path),
+            (false, false) => format!("{parent}.{}", self.path),
+        };
+        self
+    }
+}
+
+impl fmt::Display for RulesetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}", self.path)?;
+            if let Some(column) = self.column {
+                write!(f, " (column {column})")?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Checks that everything a ruleset refers to is defined
+pub(crate) fn validate(ruleset: &Ruleset) -> Vec<RulesetError> {
+    let mut errors = vec![];
+
+    let is_state = |name: &str| ruleset.states.contains_key(name);
+    let is_defined = |name: &str| is_state(name) || ruleset.groups.contains_key(name);
+
+    if !is_state(&ruleset.default_state) {
+        errors.push(RulesetError::new(
+            "default_state",
+            format!("undefined state `{}`", ruleset.default_state),
+        ));
+    }
+
+    for (group, members) in &ruleset.groups {
+        for (i, member) in members.iter().enumerate() {
+            if !is_state(member) {
+                errors.push(RulesetError::new(
+                    format!("groups.{group}[{i}]"),
+                    format!("undefined state `{member}`"),
+                ));
+            }
+        }
+    }
+
+    // Generated states are checked through the family's templates instead
+    let generated: HashSet<String> = ruleset
+        .families
+        .iter()
+        .flat_map(|(name, family)| (0..family.count).map(|i| family.member(name, i)))
+        .collect();
+
+    for (name, state) in &ruleset.states {
+        if generated.contains(name) {
+            continue;
+        }
+
+        let path = format!("states.{name}");
+        validate_colour(&mut errors, &format!("{path}.colour"), &state.colour);
+
+        for (source, conditions, outcome) in state.parsed_rules() {
+            let rule_path = format!("{path}.rules[{source:?}]");
+            validate_conditions(&mut errors, &rule_path, source, conditions, &is_defined);
+            validate_outcome(&mut errors, &rule_path, outcome, &is_state);
+        }
+
+        if let Some(otherwise) = &state.otherwise {
+            validate_outcome(
+                &mut errors,
+                &format!("{path}.otherwise"),
+                otherwise,
+                &is_state,
+            );
+        }
+    }
+
+    for (name, family) in &ruleset.families {
+        let path = format!("families.{name}");
+        let is_template_defined = |s: &str| s == "next" || s == "prev" || is_defined(s);
+        let is_template_state = |s: &str| s == "$next" || s == "$prev" || is_state(s);
+
+        for (i, colour) in family.colours.iter().enumerate() {
+            validate_colour(&mut errors, &format!("{path}.colours[{i}]"), colour);
+        }
+
+        for (source, outcome) in &family.rules {
+            let rule_path = format!("{path}.rules[{source:?}]");
+            // Templates were already checked to parse when the family was expanded
+            if let Ok(conditions) = parse_conditions(source) {
+                validate_conditions(
+                    &mut errors,
+                    &rule_path,
+                    source,
+                    &conditions,
+                    &is_template_defined,
+                );
+            }
+            validate_outcome(&mut errors, &rule_path, outcome, &is_template_state);
+        }
+
+        if let Some(otherwise) = &family.otherwise {
+            validate_outcome(
+                &mut errors,
+                &format!("{path}.otherwise"),
+                otherwise,
+                &is_template_state,
+            );
+        }
+    }
+
+    errors.sort_by(|a, b| a.path.cmp(&b.path).then(a.column.cmp(&b.column)));
+    errors
+}
+
+fn validate_conditions(
+    errors: &mut Vec<RulesetError>,
+    path: &str,
+    source: &str,
+    conditions: &[Condition],
+    is_defined: &dyn Fn(&str) -> bool,
+) {
+    let references = conditions.iter().flat_map(|c| {
+        let compare_to = match &c.compare_to {
+            CompareTo::State(state) => Some(state),
+            CompareTo::Value(_) => None,
+        };
+        std::iter::once(&c.state).chain(compare_to)
+    });
+
+    for reference in references {
+        let name = match reference {
+            StateRef::Named(name) | StateRef::AnyExcept(name) => name,
+            StateRef::Current | StateRef::NonDefault => continue,
+        };
+
+        if !is_defined(name) {
+            let mut error = RulesetError::new(path, format!("undefined state `{name}`"));
+            if let Some(column) = find_name(source, name) {
+                error = error.at_column(column);
+            }
+            errors.push(error);
+        }
+    }
+}
+
+fn validate_outcome(
+    errors: &mut Vec<RulesetError>,
+    path: &str,
+    outcome: &RulesetOutcome,
+    is_state: &dyn Fn(&str) -> bool,
+) {
+    if !is_state(&outcome.next) {
+        errors.push(RulesetError::new(
+            format!("{path}.next"),
+            format!("undefined state `{}`", outcome.next),
+        ));
+    }
+
+    if let Some(paint) = &outcome.paint {
+        validate_colour(errors, &format!("{path}.paint"), paint);
+    }
+}
+
+fn validate_colour(errors: &mut Vec<RulesetError>, path: &str, colour: &RulesetColour) {
+    if let RulesetColour::Hex(hex) = colour {
+        if !colour.is_valid() {
+            errors.push(RulesetError::new(
+                path,
+                format!("invalid hex colour {hex:?}, expected #RRGGBB or #RRGGBBAA"),
+            ));
+        }
+    }
+}
+
+/// The 1-based column in characters of the first whole-word occurrence of `name` in a rule
+fn find_name(source: &str, name: &str) -> Option<usize> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    source.match_indices(name).find_map(|(index, _)| {
+        let before = source[..index].chars().next_back();
+        let after = source[index + name.len()..].chars().next();
+        let whole_word = !before.is_some_and(is_name_char) && !after.is_some_and(is_name_char);
+        whole_word.then(|| source[..index].chars().count() + 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors_for(json: &str) -> Vec<RulesetError> {
+        Ruleset::from_json(json).expect_err("Ruleset should be invalid")
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let errors = errors_for(
+            r##"{
+                "default_state": "missing",
+                "neighbourhood": "Individual",
+                "states": {
+                    "dead": {
+                        "colour": "#00ff",
+                        "rules": {
+                            "$alive == 3": { "next": "alive" },
+                            "$dead == 3 junk": { "next": "dead" }
+                        }
+                    },
+                    "other": {
+                        "colour": [0, 0, 0, 0],
+                        "rules": { "$a == ": { "next": "dead" } }
+                    }
+                }
+            }"##,
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                RulesetError::new("default_state", "undefined state `missing`"),
+                RulesetError::new(
+                    "states.dead.colour",
+                    r##"invalid hex colour "#00ff", expected #RRGGBB or #RRGGBBAA"##
+                ),
+                RulesetError::new(
+                    r#"states.dead.rules["$alive == 3"]"#,
+                    "undefined state `alive`"
+                )
+                .at_column(2),
+                RulesetError::new(
+                    r#"states.dead.rules["$alive == 3"].next"#,
+                    "undefined state `alive`"
+                ),
+                RulesetError::new(
+                    r#"states.dead.rules["$dead == 3 junk"]"#,
+                    r#"expected `;` or the end of the rule, found "junk""#
+                )
+                .at_column(12),
+                RulesetError::new(
+                    r#"states.other.rules["$a == "]"#,
+                    "expected a state reference or number, found end of rule"
+                )
+                .at_column(7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_undefined_references() {
+        let errors = errors_for(
+            r##"{
+                "default_state": "missing",
+                "neighbourhood": "Individual",
+                "groups": { "alive": ["dead", "ghost"] },
+                "states": {
+                    "dead": {
+                        "colour": "#00ff",
+                        "rules": {
+                            "$dead == 1 ; $any_except(zombie) > 2": { "next": "zombie" }
+                        },
+                        "otherwise": { "next": "dead", "paint": "red" }
+                    }
+                }
+            }"##,
+        );
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "default_state: undefined state `missing`",
+                "groups.alive[1]: undefined state `ghost`",
+                "states.dead.colour: invalid hex colour \"#00ff\", expected #RRGGBB or #RRGGBBAA",
+                "states.dead.otherwise.paint: invalid hex colour \"red\", expected #RRGGBB or #RRGGBBAA",
+                "states.dead.rules[\"$dead == 1 ; $any_except(zombie) > 2\"] (column 26): undefined state `zombie`",
+                "states.dead.rules[\"$dead == 1 ; $any_except(zombie) > 2\"].next: undefined state `zombie`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_reference_columns_count_characters() {
+        let errors = errors_for(
+            r#"{
+                "default_state": "été",
+                "neighbourhood": "Individual",
+                "states": {
+                    "été": {
+                        "colour": [0, 0, 0, 0],
+                        "rules": { "$été == 1 ; $hiver > 0": { "next": "été" } }
+                    }
+                }
+            }"#,
+        );
+
+        // Counted in characters, as parse errors are, rather than bytes
+        assert_eq!(
+            errors,
+            vec![RulesetError::new(
+                r#"states.été.rules["$été == 1 ; $hiver > 0"]"#,
+                "undefined state `hiver`"
+            )
+            .at_column(14)]
+        );
+    }
+
+    #[test]
+    fn test_family_templates() {
+        let errors = errors_for(
+            r#"{
+                "default_state": "step_1",
+                "neighbourhood": "Individual",
+                "families": {
+                    "step": {
+                        "count": 3,
+                        "colours": [[0, 0, 0, 0]],
+                        "rules": { "$next > 0 ; $nope == 1": { "next": "$prev" } },
+                        "otherwise": { "next": "$last" }
+                    }
+                }
+            }"#,
+        );
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "families.step.otherwise.next: undefined state `$last`",
+                "families.step.rules[\"$next > 0 ; $nope == 1\"] (column 14): undefined state `nope`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bundled_rulesets_are_valid() {
+        for json in [
+            include_str!("../rulesets/game_of_life.json"),
+            include_str!("../rulesets/highlife.json"),
+            include_str!("../rulesets/immigration.json"),
+            include_str!("../rulesets/cyclic.json"),
+            include_str!("../rulesets/wireworld.json"),
+        ] {
+            assert!(Ruleset::from_json(json).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_find_name() {
+        assert_eq!(find_name("$alive_2 > $alive", "alive"), Some(13));
+        assert_eq!(find_name("$any_except(alive) > 1", "alive"), Some(13));
+        assert_eq!(find_name("$alive_2 > 1", "alive"), None);
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use macroquad::{
+    prelude::*,
+    rand::{ChooseRandom, RandGenerator},
+};
+
+use crate::{
+    pattern::Pattern,
+    ruleset::{Ruleset, RulesetColour},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Cell {
+    pub state: String,
+    pub colour: Color,
+}
+
+impl From<RulesetColour> for Color {
+    fn from(value: RulesetColour) -> Self {
+        let [r, g, b, a] = value.rgba();
+        color_u8!(r, g, b, a)
+    }
+}
+
+/// Source of `World::revision`, shared so that no two worlds ever have the same revision
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) struct World {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+    pub ruleset: Ruleset,
+    /// How many generations have passed since the world was created or reset
+    pub generation: u64,
+    /// Changes whenever cells are changed through `World`'s methods, so views know to redraw
+    pub revision: u64,
+}
+
+impl World {
+    pub fn new((width, height): (usize, usize), ruleset: Ruleset) -> Option<Self> {
+        let default_state = ruleset.default_state.clone();
+        if let Some(state) = ruleset.states.get(&default_state) {
+            Some(World {
+                width,
+                height,
+                cells: vec![
+                    Cell {
+                        state: default_state,
+                        colour: state.colour.clone().into()
+                    };
+                    width * height
+                ],
+                ruleset,
+                generation: 0,
+                revision: NEXT_REVISION.fetch_add(1, Ordering::Relaxed),
+            })
+        } else {
+            println!("No States defined");
+            None
+        }
+    }
+
+    /// A copy of the world under another ruleset, with cells in states it no longer defines
+    /// set to its default state
+    pub fn with_ruleset(&self, ruleset: Ruleset) -> Option<World> {
+        let mut world = World::new((self.width, self.height), ruleset)?;
+        world.generation = self.generation;
+
+        for (cell, new_cell) in self.cells.iter().zip(&mut world.cells) {
+            let Some(state) = world.ruleset.states.get(&cell.state) else {
+                continue;
+            };
+            // Painted cells keep their paint, the rest pick up any change of colour
+            let old_colour = self
+                .ruleset
+                .states
+                .get(&cell.state)
+                .map(|old| Color::from(old.colour.clone()));
+            *new_cell = Cell {
+                state: cell.state.clone(),
+                colour: match old_colour {
+                    Some(colour) if colour != cell.colour => cell.colour,
+                    _ => state.colour.clone().into(),
+                },
+            };
+        }
+
+        Some(world)
+    }
+
+    /// Marks the cells as changed
+    pub fn touch(&mut self) {
+        self.revision = NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&mut self) {
+        let default_state = self.ruleset.default_state.clone();
+        if let Some(state) = self.ruleset.states.get(&default_state) {
+            self.cells = vec![
+                Cell {
+                    state: default_state,
+                    colour: state.colour.clone().into()
+                };
+                self.width * self.height
+            ];
+            self.generation = 0;
+            self.touch();
+        }
+    }
+
+    /// Changes the size of the world, cropping cells that fall outside it and padding with the
+    /// default state, with the old top left corner moved to `offset`
+    pub fn resize(
+        &mut self,
+        (width, height): (usize, usize),
+        (offset_x, offset_y): (isize, isize),
+    ) {
+        let Some(default_cell) = self.state_cell(0) else {
+            return;
+        };
+        let mut cells = vec![default_cell; width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                let (old_x, old_y) = (x as isize - offset_x, y as isize - offset_y);
+                if (0..self.width as isize).contains(&old_x)
+                    && (0..self.height as isize).contains(&old_y)
+                {
+                    cells[y * width + x] = self.get_cell(old_x, old_y).clone();
+                }
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.cells = cells;
+        self.touch();
+    }
+
+    pub fn randomise(&mut self) {
+        let states: Vec<String> = self.ruleset.states.keys().cloned().collect();
+        for cell in &mut self.cells {
+            let name = states.choose().unwrap();
+            let state = self.ruleset.states.get(name).expect("Unreachable");
+            cell.state = name.clone();
+            cell.colour = state.colour.clone().into();
+        }
+        self.touch();
+    }
+
+    pub fn get_index(&self, x: isize, y: isize) -> usize {
+        let x = x.rem_euclid(self.width as isize);
+        let y = y.rem_euclid(self.height as isize);
+        (y * self.width as isize + x) as usize
+    }
+
+    pub fn get_cell(&self, x: isize, y: isize) -> &Cell {
+        &self.cells[self.get_index(x, y)]
+    }
+
+    pub fn set_cell(&mut self, x: isize, y: isize, cell: Cell) {
+        let index = self.get_index(x, y);
+        self.cells[index] = cell;
+        self.touch();
+    }
+
+    pub fn get_neighbourhood(&self, x: isize, y: isize) -> HashMap<String, usize> {
+        let mut neighbour_counts = HashMap::new();
+
+        for (dx, dy) in self.ruleset.neighbourhood.get_neighbours() {
+            let (a, b) = (x + dx, y + dy);
+            let cell = self.get_cell(a, b);
+
+            // TODO: get rid of this clone
+            neighbour_counts
+                .entry(cell.state.clone())
+                .and_modify(|v| *v += 1)
+                .or_insert(1);
+        }
+
+        neighbour_counts
+    }
+
+    pub fn next_generation(&mut self) {
+        let mut new_generation = self.cells.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (x, y) = (x as isize, y as isize);
+                let neighbour_counts = self.get_neighbourhood(x, y);
+                let current_cell = self.get_cell(x, y);
+
+                // TODO: Remove this clone
+                if self.ruleset.states.contains_key(&current_cell.state) {
+                    if let Some(next) = self
+                        .ruleset
+                        .transition(&current_cell.state, &neighbour_counts)
+                    {
+                        let Some(next_state) = self.ruleset.states.get(&next.next) else {
+                            println!("No {} defined", next.next);
+                            continue;
+                        };
+                        let colour = next.paint.as_ref().unwrap_or(&next_state.colour);
+                        new_generation[self.get_index(x, y)] = Cell {
+                            state: next.next.clone(),
+                            colour: colour.clone().into(),
+                        };
+                        // TODO: can I remove these clones?
+                    }
+                } else {
+                    println!(
+                        "No state rules found in {:?} with ruleset {:?}",
+                        current_cell, self.ruleset
+                    );
+                }
+            }
+        }
+
+        self.cells = new_generation;
+        self.generation += 1;
+        self.touch();
+    }
+
+    /// Spawns a group at a random position, for periodic spawns. Where it lands and which cells
+    /// it sets come only from `seed` and `count`, the number of spawns before it, so a restored
+    /// snapshot goes on to spawn the same cells as the run it was taken from.
+    pub fn spawn_random(&mut self, size: isize, state: &str, density: f32, seed: u64, count: u64) {
+        let rng = RandGenerator::new();
+        rng.srand(seed ^ count.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let x = rng.rand() as usize % self.width;
+        let y = rng.rand() as usize % self.height;
+        self.spawn_group(x as isize, y as isize, size, state, density, &rng);
+    }
+
+    /// Scatters cells of a state over a square around (x, y), each with probability `density`
+    pub fn spawn_group(
+        &mut self,
+        x: isize,
+        y: isize,
+        size: isize,
+        state: &str,
+        density: f32,
+        rng: &RandGenerator,
+    ) {
+        let state_name = state.to_string();
+        let state_definition = self.ruleset.states.get(state);
+        if state_definition.is_none() {
+            println!("No {} defined", state);
+            return;
+        }
+
+        let state_colour = state_definition.unwrap().colour.clone();
+
+        if size == 0 {
+            return;
+        } else if size == 1 {
+            self.set_cell(
+                x,
+                y,
+                Cell {
+                    state: state_name,
+                    colour: state_colour.into(),
+                },
+            );
+            return;
+        }
+
+        for dx in 0..size {
+            for dy in 0..size {
+                let nx = x + dx - 1;
+                let ny = y + dy - 1;
+                if nx < (self.width as isize)
+                    && ny < (self.height as isize)
+                    && rng.gen_range(0.0, 1.0) < density
+                {
+                    self.set_cell(
+                        nx,
+                        ny,
+                        Cell {
+                            state: state_name.clone(),
+                            colour: state_colour.clone().into(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Sets the cell at (x, y) and every cell connected to it through edges in the same state,
+    /// without wrapping around the world's edges. Returns how many cells changed.
+    pub fn flood_fill(&mut self, x: isize, y: isize, cell: &Cell) -> usize {
+        let in_world = |x: isize, y: isize| {
+            (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&y)
+        };
+        if !in_world(x, y) {
+            return 0;
+        }
+        let target = self.get_cell(x, y).state.clone();
+        if target == cell.state {
+            return 0;
+        }
+
+        let mut filled = 0;
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if !in_world(x, y) {
+                continue;
+            }
+            let index = self.get_index(x, y);
+            if self.cells[index].state != target {
+                continue;
+            }
+            self.cells[index] = cell.clone();
+            filled += 1;
+            stack.extend([(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+        }
+        self.touch();
+
+        filled
+    }
+
+    /// The cell for the `index`th state of `Ruleset::state_names`
+    pub fn state_cell(&self, index: usize) -> Option<Cell> {
+        let name = *self.ruleset.state_names().get(index)?;
+        let state = self.ruleset.states.get(name)?;
+        Some(Cell {
+            state: name.to_string(),
+            colour: state.colour.clone().into(),
+        })
+    }
+
+    /// Places the non-default cells of a pattern with its top left corner at (x, y)
+    pub fn stamp(&mut self, pattern: &Pattern, x: isize, y: isize) -> Result<(), String> {
+        let cells: Vec<Cell> = (0..=pattern.max_state())
+            .map(|i| self.state_cell(i))
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                format!(
+                    "Pattern uses state {} but the ruleset only has {} states",
+                    pattern.max_state(),
+                    self.ruleset.states.len()
+                )
+            })?;
+
+        for py in 0..pattern.height {
+            for px in 0..pattern.width {
+                let state = pattern.get(px, py);
+                if state != 0 {
+                    self.set_cell(x + px as isize, y + py as isize, cells[state].clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets every cell of a rectangle with its top left corner at (x, y)
+    pub fn fill(&mut self, x: isize, y: isize, width: usize, height: usize, cell: &Cell) {
+        for dy in 0..height as isize {
+            for dx in 0..width as isize {
+                self.set_cell(x + dx, y + dy, cell.clone());
+            }
+        }
+    }
+
+    /// Gives each cell of a rectangle a random state other than the default one with
+    /// probability `density`, and the default state otherwise
+    pub fn scatter(&mut self, x: isize, y: isize, width: usize, height: usize, density: f32) {
+        let states: Vec<Cell> = (1..self.ruleset.states.len())
+            .filter_map(|i| self.state_cell(i))
+            .collect();
+        let Some(default_cell) = self.state_cell(0) else {
+            return;
+        };
+
+        for dy in 0..height as isize {
+            for dx in 0..width as isize {
+                let cell = match states.choose() {
+                    Some(cell) if rand::gen_range(0.0, 1.0) < density => cell.clone(),
+                    _ => default_cell.clone(),
+                };
+                self.set_cell(x + dx, y + dy, cell);
+            }
+        }
+    }
+
+    /// Each distinct state and colour in the world, since `paint` can colour a cell
+    /// differently to its state, and the palette index of every cell
+    pub fn palette(&self) -> (Vec<(String, [u8; 4])>, Vec<usize>) {
+        let mut palette = vec![];
+        let mut indices: HashMap<(&str, [u8; 4]), usize> = HashMap::new();
+
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let colour: [u8; 4] = cell.colour.into();
+                *indices
+                    .entry((cell.state.as_str(), colour))
+                    .or_insert_with(|| {
+                        palette.push((cell.state.clone(), colour));
+                        palette.len() - 1
+                    })
+            })
+            .collect();
+
+        (palette, cells)
+    }
+
+    /// Replaces every cell, using a palette and indices as returned by `palette`
+    pub fn set_palette_cells(
+        &mut self,
+        palette: &[(String, [u8; 4])],
+        cells: &[usize],
+    ) -> Result<(), String> {
+        if cells.len() != self.width * self.height {
+            return Err(format!(
+                "Expected {} cells for a {}x{} world, found {}",
+                self.width * self.height,
+                self.width,
+                self.height,
+                cells.len()
+            ));
+        }
+
+        let palette: Vec<Cell> = palette
+            .iter()
+            .map(|(state, [r, g, b, a])| {
+                if self.ruleset.states.contains_key(state) {
+                    Ok(Cell {
+                        state: state.clone(),
+                        colour: Color::from_rgba(*r, *g, *b, *a),
+                    })
+                } else {
+                    Err(format!("Undefined state `{state}` in palette"))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.cells = cells
+            .iter()
+            .map(|i| {
+                palette
+                    .get(*i)
+                    .cloned()
+                    .ok_or_else(|| format!("Palette index {i} out of range"))
+            })
+            .collect::<Result<_, _>>()?;
+        self.touch();
+
+        Ok(())
+    }
+
+    /// Copies a rectangle of the world, with its top left corner at (x, y), into a pattern
+    pub fn extract(&self, x: isize, y: isize, width: usize, height: usize) -> Pattern {
+        let indices: HashMap<&str, usize> = self
+            .ruleset
+            .state_names()
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, i))
+            .collect();
+
+        let mut pattern = Pattern::new(width, height);
+        for py in 0..height {
+            for px in 0..width {
+                let cell = self.get_cell(x + px as isize, y + py as isize);
+                pattern.set(px, py, *indices.get(cell.state.as_str()).unwrap_or(&0));
+            }
+        }
+
+        pattern
+    }
+
+    /// The smallest rectangle containing every non-default cell, as (x, y, width, height)
+    pub fn live_bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let default_state = &self.ruleset.default_state;
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (self.width, self.height, 0, 0);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.cells[y * self.width + x].state != *default_state {
+                    min_x = min_x.min(x);
+                    min_y = min_y.min(y);
+                    max_x = max_x.max(x);
+                    max_y = max_y.max(y);
+                }
+            }
+        }
+
+        (min_x <= max_x).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+    }
+
+    /// Copies the smallest rectangle containing every non-default cell into a pattern
+    pub fn to_pattern(&self) -> Pattern {
+        match self.live_bounds() {
+            Some((x, y, width, height)) => self.extract(x as isize, y as isize, width, height),
+            None => Pattern::new(0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pattern::rle,
+        test_utils::{world, LIFE, WIREWORLD},
+    };
+
+    #[test]
+    fn test_stamp_and_extract() {
+        let mut world = world(LIFE, (10, 10), None);
+        let glider = rle::parse("x = 3, y = 3\nbo$2bo$3o!").unwrap();
+
+        world.stamp(&glider, 4, 2).unwrap();
+
+        assert_eq!(world.get_cell(5, 2).state, "alive");
+        assert_eq!(world.get_cell(4, 2).state, "dead");
+        assert_eq!(world.extract(4, 2, 3, 3), glider);
+        assert_eq!(world.to_pattern(), glider);
+    }
+
+    #[test]
+    fn test_fill_and_scatter() {
+        let mut world = world(WIREWORLD, (6, 6), None);
+        let head = world.state_cell(1).unwrap();
+
+        world.fill(1, 1, 3, 2, &head);
+        assert_eq!(world.live_bounds(), Some((1, 1, 3, 2)));
+
+        world.scatter(0, 0, 6, 6, 0.0);
+        assert_eq!(world.live_bounds(), None);
+
+        world.scatter(2, 3, 2, 2, 1.0);
+        assert_eq!(world.live_bounds(), Some((2, 3, 2, 2)));
+        assert_eq!(
+            world.to_pattern().cells.iter().filter(|&&c| c == 0).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let mut world = world(WIREWORLD, (6, 5), None);
+        let head = world.state_cell(1).unwrap();
+        let conductor = world.state_cell(3).unwrap();
+        // A closed loop of conductor, with the fill spreading inside it only
+        world
+            .stamp(&rle::parse("x = 4, y = 4\n4C$C2.C$C2.C$4C!").unwrap(), 1, 0)
+            .unwrap();
+
+        assert_eq!(world.flood_fill(2, 1, &head), 4);
+        assert_eq!(world.get_cell(3, 2).state, head.state);
+        assert_eq!(world.get_cell(0, 0).state, "empty");
+        assert_eq!(world.flood_fill(2, 1, &head), 0);
+
+        // Everything outside the loop is connected through the bottom row
+        assert_eq!(world.flood_fill(0, 0, &conductor), 30 - 16);
+        assert_eq!(world.flood_fill(9, 9, &head), 0);
+    }
+
+    #[test]
+    fn test_stamp_wraps() {
+        let mut world = world(WIREWORLD, (4, 4), None);
+        let pattern = rle::parse("x = 3, y = 1\nABC!").unwrap();
+
+        world.stamp(&pattern, 2, 3).unwrap();
+
+        assert_eq!(world.get_cell(2, 3).state, "head");
+        assert_eq!(world.get_cell(3, 3).state, "tail");
+        assert_eq!(world.get_cell(0, 3).state, "conductor");
+    }
+
+    #[test]
+    fn test_stamp_unknown_state() {
+        let mut world = world(LIFE, (4, 4), None);
+        let pattern = rle::parse("x = 1, y = 1\nB!").unwrap();
+
+        assert!(world.stamp(&pattern, 0, 0).is_err());
+        assert_eq!(world.to_pattern(), Pattern::new(0, 0));
+    }
+
+    #[test]
+    fn test_with_ruleset() {
+        let mut world = world(WIREWORLD, (4, 1), None);
+        world
+            .stamp(&rle::parse("x = 4, y = 1\nABCA!").unwrap(), 0, 0)
+            .unwrap();
+        world.cells[3].colour = Color::from_rgba(1, 2, 3, 255);
+        world.generation = 7;
+
+        let mut json: serde_json::Value = serde_json::from_str(WIREWORLD).unwrap();
+        json["states"].as_object_mut().unwrap().remove("tail");
+        json["states"]["head"]["otherwise"]["next"] = "conductor".into();
+        json["states"]["conductor"]["colour"] = serde_json::json!([0, 255, 0, 255]);
+        let ruleset = Ruleset::from_json(&json.to_string()).unwrap();
+
+        let reloaded = world.with_ruleset(ruleset).unwrap();
+        let states: Vec<&str> = reloaded.cells.iter().map(|c| c.state.as_str()).collect();
+
+        assert_eq!(states, vec!["head", "empty", "conductor", "head"]);
+        assert_eq!(reloaded.cells[2].colour, Color::from_rgba(0, 255, 0, 255));
+        assert_eq!(reloaded.cells[3].colour, Color::from_rgba(1, 2, 3, 255));
+        assert_eq!(reloaded.generation, 7);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut world = world(LIFE, (4, 4), None);
+        let glider = rle::parse("x = 3, y = 3\nbo$2bo$3o!").unwrap();
+        world.stamp(&glider, 1, 1).unwrap();
+
+        world.resize((8, 6), (2, 1));
+        assert_eq!((world.width, world.height), (8, 6));
+        assert_eq!(world.cells.len(), 48);
+        assert_eq!(world.extract(3, 2, 3, 3), glider);
+        assert_eq!(world.to_pattern(), glider);
+
+        // Cropping away the top row of the glider
+        world.resize((3, 2), (-3, -3));
+        assert_eq!(world.to_pattern().cells, vec![0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_revision() {
+        let other = world(LIFE, (4, 4), None);
+        let mut world = world(LIFE, (4, 4), None);
+        assert_ne!(world.revision, other.revision);
+
+        let mut revisions = vec![world.revision];
+        world.set_cell(1, 1, world.state_cell(1).unwrap());
+        revisions.push(world.revision);
+        world.next_generation();
+        revisions.push(world.revision);
+        world.resize((5, 5), (0, 0));
+        revisions.push(world.revision);
+        world.reset();
+        revisions.push(world.revision);
+
+        revisions.dedup();
+        assert_eq!(revisions.len(), 5);
+    }
+}
@@ -0,0 +1,203 @@
+use macroquad::prelude::*;
+
+use crate::{camera::Camera, pattern::Pattern, world::World};
+
+/// Largest texture side, beyond which the world is drawn at a lower level of detail
+const MAX_TEXTURE_SIZE: usize = 4096;
+
+/// Draws a world as a single textured quad, uploading its cells again only when they change
+pub(crate) struct WorldView {
+    texture: Option<Texture2D>,
+    /// The world revision and level of detail the texture was built from
+    built: Option<(u64, usize)>,
+}
+
+impl WorldView {
+    pub fn new() -> Self {
+        WorldView {
+            texture: None,
+            built: None,
+        }
+    }
+
+    pub fn draw(&mut self, world: &World, camera: &Camera, background: Color) {
+        let scale = level_of_detail(world, camera.zoom);
+        if self.built != Some((world.revision, scale)) {
+            let (width, height, bytes) = cell_pixels(world, scale);
+            let image = Image {
+                bytes,
+                width: width as u16,
+                height: height as u16,
+            };
+            match &self.texture {
+                Some(texture) if texture.size() == vec2(width as f32, height as f32) => {
+                    texture.update(&image)
+                }
+                _ => {
+                    let texture = Texture2D::from_image(&image);
+                    texture.set_filter(FilterMode::Nearest);
+                    self.texture = Some(texture);
+                }
+            }
+            self.built = Some((world.revision, scale));
+        }
+        let Some(texture) = &self.texture else {
+            return;
+        };
+
+        let corner = camera.world_to_screen(Vec2::ZERO);
+        let size = vec2(world.width as f32, world.height as f32) * camera.zoom;
+        draw_texture_ex(
+            texture,
+            corner.x,
+            corner.y,
+            WHITE,
+            DrawTextureParams {
+                // Each texel covers `scale` cells, with the last row and column possibly
+                // hanging a little past the world's edge
+                dest_size: Some(texture.size() * scale as f32 * camera.zoom),
+                ..Default::default()
+            },
+        );
+
+        // Cut the gaps between cells once they are big enough to show one
+        if camera.zoom >= 4.0 {
+            let gap = camera.zoom * 0.2;
+            let colour = Color {
+                a: 1.0,
+                ..background
+            };
+            let screen = vec2(screen_width(), screen_height());
+            let ((min_x, min_y), (max_x, max_y)) = camera.visible_cells(screen);
+            for x in min_x.max(0)..=max_x.min(world.width as isize) {
+                let left = camera.world_to_screen(vec2(x as f32, 0.0)).x;
+                draw_rectangle(left - gap / 2.0, corner.y, gap, size.y, colour);
+            }
+            for y in min_y.max(0)..=max_y.min(world.height as isize) {
+                let top = camera.world_to_screen(vec2(0.0, y as f32)).y;
+                draw_rectangle(corner.x, top - gap / 2.0, size.x, gap, colour);
+            }
+        }
+    }
+}
+
+/// How many cells along each side share one texel, so that zoomed out views and huge
+/// worlds don't upload more texels than there are pixels to show them
+fn level_of_detail(world: &World, zoom: f32) -> usize {
+    let by_zoom = (1.0 / zoom).ceil().max(1.0) as usize;
+    let by_size = world.width.max(world.height).div_ceil(MAX_TEXTURE_SIZE);
+    by_zoom.max(by_size).max(1)
+}
+
+/// The world's cells as RGBA bytes, with each `scale` by `scale` block reduced to one pixel
+fn cell_pixels(world: &World, scale: usize) -> (usize, usize, Vec<u8>) {
+    let default_state = &world.ruleset.default_state;
+    block_pixels(world.width, world.height, scale, |x, y| {
+        let cell = &world.cells[y * world.width + x];
+        (cell.state == *default_state, cell.colour.into())
+    })
+}
+
+/// RGBA bytes for a `width` by `height` grid, with each `scale` by `scale` block reduced to
+/// one pixel. `cell` gives whether a cell is in the default state and its colour. A block
+/// shows its first cell which is not in the default state, so that sparse patterns stay
+/// visible when zoomed out.
+pub fn block_pixels(
+    width: usize,
+    height: usize,
+    scale: usize,
+    cell: impl Fn(usize, usize) -> (bool, [u8; 4]),
+) -> (usize, usize, Vec<u8>) {
+    let blocks_wide = width.div_ceil(scale);
+    let blocks_high = height.div_ceil(scale);
+    let mut bytes = Vec::with_capacity(blocks_wide * blocks_high * 4);
+
+    for block_y in 0..blocks_high {
+        for block_x in 0..blocks_wide {
+            let cell = &cell;
+            let mut block = (block_y * scale..((block_y + 1) * scale).min(height)).flat_map(|y| {
+                (block_x * scale..((block_x + 1) * scale).min(width)).map(move |x| cell(x, y))
+            });
+            let first = block.next().expect("Blocks are never empty");
+            let (_, colour) = if first.0 {
+                block.find(|(is_default, _)| !is_default).unwrap_or(first)
+            } else {
+                first
+            };
+            bytes.extend_from_slice(&colour);
+        }
+    }
+
+    (blocks_wide, blocks_high, bytes)
+}
+
+/// A see-through texture of a pattern in the world's state colours, for showing where it
+/// would be placed, along with how many cells along each side share one texel. Empty
+/// patterns have none.
+pub fn pattern_ghost(pattern: &Pattern, world: &World) -> Option<(Texture2D, usize)> {
+    if pattern.width == 0 || pattern.height == 0 {
+        return None;
+    }
+    let colours: Vec<[u8; 4]> = (0..=pattern.max_state())
+        .map(|state| match world.state_cell(state) {
+            Some(cell) if state != 0 => {
+                let [r, g, b, a]: [u8; 4] = cell.colour.into();
+                [r, g, b, a / 2]
+            }
+            _ => [0; 4],
+        })
+        .collect();
+    let scale = pattern
+        .width
+        .max(pattern.height)
+        .div_ceil(MAX_TEXTURE_SIZE)
+        .max(1);
+    let (width, height, bytes) = block_pixels(pattern.width, pattern.height, scale, |x, y| {
+        let state = pattern.get(x, y);
+        (state == 0, colours[state])
+    });
+
+    let texture = Texture2D::from_rgba8(width as u16, height as u16, &bytes);
+    texture.set_filter(FilterMode::Nearest);
+    Some((texture, scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{world, LIFE};
+
+    #[test]
+    fn test_cell_pixels() {
+        let mut world = world(LIFE, (5, 3), None);
+        let alive = world.state_cell(1).unwrap();
+        let dead = world.state_cell(0).unwrap();
+        world.set_cell(1, 0, alive.clone());
+        world.set_cell(4, 2, alive.clone());
+        let alive: [u8; 4] = alive.colour.into();
+        let dead: [u8; 4] = dead.colour.into();
+
+        let (width, height, bytes) = cell_pixels(&world, 1);
+        assert_eq!((width, height), (5, 3));
+        assert_eq!(&bytes[4..8], alive);
+        assert_eq!(&bytes[0..4], dead);
+
+        // Any live cell in a block shows, including in the partial blocks at the edges
+        let (width, height, bytes) = cell_pixels(&world, 2);
+        assert_eq!((width, height), (3, 2));
+        let pixels: Vec<&[u8]> = bytes.chunks(4).collect();
+        assert_eq!(pixels, vec![&alive, &dead, &dead, &dead, &dead, &alive]);
+    }
+
+    #[test]
+    fn test_level_of_detail() {
+        let small = world(LIFE, (10, 10), None);
+        assert_eq!(level_of_detail(&small, 8.0), 1);
+        assert_eq!(level_of_detail(&small, 1.0), 1);
+        assert_eq!(level_of_detail(&small, 0.5), 2);
+        assert_eq!(level_of_detail(&small, 0.3), 4);
+
+        let wide = world(LIFE, (MAX_TEXTURE_SIZE * 2 + 1, 1), None);
+        assert_eq!(level_of_detail(&wide, 8.0), 3);
+    }
+}